@@ -1,19 +1,22 @@
 use rand::prelude::SliceRandom;
+use serde::Deserialize;
 
 
-#[derive(Clone)]
+#[derive(Clone, Deserialize, Debug, PartialEq)]
 pub enum Advantage {
     Player(PlayerAdvantage),
     Enemy(EnemyAdvantage)
 }
 
-#[derive(Clone)]
+#[derive(Clone, Deserialize, Debug, PartialEq)]
 pub enum PlayerAdvantage {
     DoubleJump,
     DoubleInitialHp,
+    /// Stomps and pinches hurt fish twice as much
+    StrongClaws,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Deserialize, Debug, PartialEq)]
 pub enum EnemyAdvantage {
     DoubleBite,
     DoubleSpeed
@@ -22,6 +25,7 @@ pub enum EnemyAdvantage {
 static ADVANTAGES: &[Advantage] = &[
     Advantage::Player(PlayerAdvantage::DoubleJump),
     Advantage::Player(PlayerAdvantage::DoubleInitialHp),
+    Advantage::Player(PlayerAdvantage::StrongClaws),
     Advantage::Enemy(EnemyAdvantage::DoubleBite),
     Advantage::Enemy(EnemyAdvantage::DoubleSpeed),
 ];
@@ -30,4 +34,34 @@ impl Advantage {
     pub fn random() -> Self {
         ADVANTAGES.choose(&mut rand::thread_rng()).unwrap().clone()
     }
+
+    /// Looks up an advantage by its variant name, like `DoubleSpeed`
+    pub fn from_name(name: &str) -> Option<Self> {
+        ADVANTAGES
+            .iter()
+            .find(|advantage| advantage.name() == name)
+            .cloned()
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Advantage::Player(PlayerAdvantage::DoubleJump) => "DoubleJump",
+            Advantage::Player(PlayerAdvantage::DoubleInitialHp) => "DoubleInitialHp",
+            Advantage::Player(PlayerAdvantage::StrongClaws) => "StrongClaws",
+            Advantage::Enemy(EnemyAdvantage::DoubleBite) => "DoubleBite",
+            Advantage::Enemy(EnemyAdvantage::DoubleSpeed) => "DoubleSpeed",
+        }
+    }
+
+    pub fn favours_enemies(&self) -> bool {
+        matches!(self, Advantage::Enemy(_))
+    }
+
+    /// Picks one of `allowed`, or any advantage if the list is empty
+    pub fn random_from(allowed: &[Advantage]) -> Self {
+        allowed
+            .choose(&mut rand::thread_rng())
+            .cloned()
+            .unwrap_or_else(Advantage::random)
+    }
 }
\ No newline at end of file
@@ -0,0 +1,520 @@
+//! Enemy behaviour. Every fish runs a small state machine, tuned per archetype
+//! in `assets/enemies.fish.ron`.
+
+use crate::advantage::{Advantage, EnemyAdvantage};
+use crate::animation::switch_clip;
+use crate::enemy::{Bite, Borders, Direction, Enemy, FishAnimations, Speed};
+use crate::fish::AiDef;
+use crate::player::Player;
+use crate::special::Special;
+use crate::tmx::{CollisionTiles, Waypoints};
+use benimator::SpriteSheetAnimation;
+use bevy::prelude::*;
+use heron::Velocity;
+
+/// Fish this close to where they are going stop, instead of turning back and forth
+const ARRIVE_DISTANCE: f32 = 4.0;
+
+/// How fast a stunned fish slows down after being knocked back, fraction of speed per second
+const KNOCKBACK_DRAG: f32 = 4.0;
+
+const ROUTE_MARKER_COLOR: Color = Color::rgba(1.0, 0.3, 0.2, 0.8);
+
+#[derive(Component, Debug)]
+pub enum AiState {
+    /// Swims between patrol borders
+    Patrol,
+    /// Saw the player, faces them for a moment before the chase
+    Notice(Timer),
+    Chase {
+        /// Seconds since the player was last seen
+        lost_for: f32,
+    },
+    /// Touching the player, bites on its own cooldown
+    Bite,
+    /// Backs off after the player escaped a bite
+    Cooldown(Timer),
+    /// Gave up the chase, swims back to where it was placed
+    ReturnHome,
+    /// Hit by the player, drifts with the knockback and does not bite
+    Stunned(Timer),
+}
+
+/// Where the fish was placed in the level
+#[derive(Component)]
+pub struct Home(pub Vec2);
+
+#[derive(Component)]
+pub struct Tuning(pub AiDef);
+
+/// Fish that float in open water and move on both axes, instead of along the floor
+#[derive(Component)]
+pub struct Swims;
+
+/// Waypoints a fish patrols instead of swimming between its borders
+#[derive(Component)]
+pub struct PatrolPath {
+    waypoints: Waypoints,
+    next: usize,
+    /// Open paths are swum back and forth
+    backwards: bool,
+}
+
+impl PatrolPath {
+    pub fn new(waypoints: Waypoints) -> Self {
+        PatrolPath {
+            waypoints,
+            next: 0,
+            backwards: false,
+        }
+    }
+
+    fn target(&self) -> Vec2 {
+        self.waypoints.points[self.next]
+    }
+
+    fn advance(&mut self) {
+        let (next, backwards) = self.waypoints.step(self.next, self.backwards);
+        self.next = next;
+        self.backwards = backwards;
+    }
+}
+
+/// Tile centers on the way around walls to the chase or home target, nearest last
+#[derive(Component, Default)]
+pub struct Route {
+    points: Vec<Vec2>,
+    /// Tile the route was planned to, it is planned again when the target moves to another tile
+    goal: Option<(i32, i32)>,
+}
+
+impl Route {
+    fn clear(&mut self) {
+        self.points.clear();
+        self.goal = None;
+    }
+
+    /// Next point to go to, dropping points already reached
+    fn next(&mut self, position: Vec2, swims: bool) -> Option<Vec2> {
+        while let Some(&point) = self.points.last() {
+            if reach(position, point, swims).length() > ARRIVE_DISTANCE {
+                return Some(point);
+            }
+            self.points.pop();
+        }
+        None
+    }
+}
+
+/// Sprite of the route debug overlay
+#[derive(Component)]
+pub struct RouteMarker;
+
+/// What a fish knows about itself and the player this frame
+struct Senses {
+    sees_player: bool,
+    /// Player is close enough to home to be worth chasing
+    player_in_territory: bool,
+    biting: bool,
+    beyond_leash: bool,
+    at_home: bool,
+}
+
+/// Offset from `from` to `to` along the axes a fish can move on
+fn reach(from: Vec2, to: Vec2, swims: bool) -> Vec2 {
+    let offset = to - from;
+    if swims {
+        offset
+    } else {
+        Vec2::new(offset.x, 0.0)
+    }
+}
+
+#[allow(clippy::type_complexity)]
+pub fn update_state(
+    time: Res<Time>,
+    collision_tiles: Option<Res<CollisionTiles>>,
+    player: Query<&Transform, With<Player>>,
+    mut enemies: Query<
+        (
+            &mut AiState,
+            &Transform,
+            &Home,
+            &Bite,
+            &Tuning,
+            Option<&Swims>,
+        ),
+        With<Enemy>,
+    >,
+) {
+    let player_position = match player.get_single() {
+        Ok(transform) => transform.translation.truncate(),
+        Err(_) => return,
+    };
+
+    for (mut state, transform, home, bite, tuning, swims) in enemies.iter_mut() {
+        let tuning = &tuning.0;
+        let position = transform.translation.truncate();
+        let senses = Senses {
+            sees_player: position.distance(player_position) <= tuning.sight_range
+                && collision_tiles
+                    .as_ref()
+                    .map_or(true, |tiles| tiles.line_of_sight(position, player_position)),
+            player_in_territory: player_position.distance(home.0) <= tuning.leash_range,
+            biting: bite.is_biting(),
+            beyond_leash: position.distance(home.0) > tuning.leash_range,
+            at_home: reach(position, home.0, swims.is_some()).length() <= ARRIVE_DISTANCE,
+        };
+
+        if let Some(next) = next_state(&mut state, &senses, tuning, &time) {
+            debug!("{:?} -> {:?}", *state, next);
+            *state = next;
+        }
+    }
+}
+
+fn next_state(
+    state: &mut AiState,
+    senses: &Senses,
+    tuning: &AiDef,
+    time: &Time,
+) -> Option<AiState> {
+    let notice = || AiState::Notice(Timer::from_seconds(tuning.notice_time, false));
+    let wants_player = senses.sees_player && senses.player_in_territory;
+
+    if senses.biting && !matches!(state, AiState::Bite | AiState::Stunned(_)) {
+        return Some(AiState::Bite);
+    }
+
+    match state {
+        AiState::Patrol => wants_player.then(notice),
+        AiState::Notice(timer) => {
+            timer.tick(time.delta());
+            if !senses.sees_player && senses.at_home {
+                Some(AiState::Patrol)
+            } else if !senses.sees_player {
+                Some(AiState::ReturnHome)
+            } else if timer.finished() {
+                Some(AiState::Chase { lost_for: 0.0 })
+            } else {
+                None
+            }
+        }
+        AiState::Chase { lost_for } => {
+            if senses.sees_player {
+                *lost_for = 0.0;
+            } else {
+                *lost_for += time.delta_seconds();
+            }
+            (*lost_for > tuning.memory || senses.beyond_leash).then(|| AiState::ReturnHome)
+        }
+        AiState::Bite => (!senses.biting)
+            .then(|| AiState::Cooldown(Timer::from_seconds(tuning.cooldown_time, false))),
+        AiState::Cooldown(timer) | AiState::Stunned(timer) => {
+            timer.tick(time.delta());
+            timer.finished().then(|| {
+                if wants_player {
+                    AiState::Chase { lost_for: 0.0 }
+                } else {
+                    AiState::ReturnHome
+                }
+            })
+        }
+        AiState::ReturnHome => {
+            if wants_player {
+                Some(notice())
+            } else if senses.at_home {
+                Some(AiState::Patrol)
+            } else {
+                None
+            }
+        }
+    }
+}
+
+/// Plans routes for fish that cannot go straight to their target because of walls
+pub fn plan_routes(
+    collision_tiles: Option<Res<CollisionTiles>>,
+    player: Query<&Transform, With<Player>>,
+    mut enemies: Query<(&AiState, &Transform, &Home, &mut Route, Option<&Swims>), With<Enemy>>,
+) {
+    let (collision_tiles, player_position) = match (collision_tiles, player.get_single()) {
+        (Some(collision_tiles), Ok(transform)) => {
+            (collision_tiles, transform.translation.truncate())
+        }
+        _ => return,
+    };
+    let bounds = collision_tiles.bounds;
+
+    for (state, transform, home, mut route, swims) in enemies.iter_mut() {
+        let target = match state {
+            AiState::Chase { .. } => player_position,
+            AiState::ReturnHome => home.0,
+            _ => {
+                route.clear();
+                continue;
+            }
+        };
+        let position = transform.translation.truncate();
+        if collision_tiles.line_of_sight(position, target) {
+            route.clear();
+            continue;
+        }
+        let goal = bounds.world_to_tile(target);
+        if route.goal == Some(goal) {
+            continue;
+        }
+
+        // Without a path the fish keeps pushing straight at the target
+        let tiles = collision_tiles
+            .find_path(bounds.world_to_tile(position), goal, swims.is_some())
+            .unwrap_or_default();
+        route.points = tiles
+            .iter()
+            .skip(1)
+            .rev()
+            .map(|&(x, y)| bounds.tile_center(x, y))
+            .collect();
+        route.goal = Some(goal);
+    }
+}
+
+/// Debug overlay with a dot on every route point, toggled with F9 in debug builds
+pub fn draw_routes(
+    mut commands: Commands,
+    mut shown: Local<bool>,
+    keys: Res<Input<KeyCode>>,
+    markers: Query<Entity, With<RouteMarker>>,
+    routes: Query<&Route>,
+) {
+    if cfg!(debug_assertions) && keys.just_pressed(KeyCode::F9) {
+        *shown = !*shown;
+    }
+    for marker in markers.iter() {
+        commands.entity(marker).despawn();
+    }
+    if !*shown {
+        return;
+    }
+
+    for point in routes.iter().flat_map(|route| route.points.iter()) {
+        commands
+            .spawn_bundle(SpriteBundle {
+                sprite: Sprite {
+                    color: ROUTE_MARKER_COLOR,
+                    custom_size: Some(Vec2::splat(4.0)),
+                    ..Default::default()
+                },
+                transform: Transform::from_xyz(point.x, point.y, 50.0),
+                ..Default::default()
+            })
+            .insert(RouteMarker);
+    }
+}
+
+/// Velocity towards an offset, zero once there
+fn heading(offset: Vec2, speed: f32) -> Vec2 {
+    if offset.length() <= ARRIVE_DISTANCE {
+        Vec2::ZERO
+    } else {
+        offset.normalize() * speed
+    }
+}
+
+/// Moves and animates fish according to their state
+#[allow(clippy::type_complexity)]
+pub fn steer(
+    time: Res<Time>,
+    advantage: Res<Advantage>,
+    player: Query<&Transform, With<Player>>,
+    mut enemies: Query<
+        (
+            &AiState,
+            &mut Direction,
+            &mut Velocity,
+            &mut Handle<SpriteSheetAnimation>,
+            &Transform,
+            &Home,
+            &Borders,
+            &Speed,
+            &Tuning,
+            &FishAnimations,
+            Option<&mut PatrolPath>,
+            Option<&Swims>,
+            &mut Route,
+            Option<&Special>,
+        ),
+        (With<Enemy>, Without<Player>),
+    >,
+) {
+    let player_position = match player.get_single() {
+        Ok(transform) => transform.translation.truncate(),
+        Err(_) => return,
+    };
+    let speed_multiplier = if matches!(
+        advantage.as_ref(),
+        Advantage::Enemy(EnemyAdvantage::DoubleSpeed)
+    ) {
+        1.7
+    } else {
+        1.0
+    };
+
+    for (
+        state,
+        mut direction,
+        mut velocity,
+        mut animation,
+        transform,
+        home,
+        borders,
+        speed,
+        tuning,
+        animations,
+        path,
+        swims,
+        mut route,
+        special,
+    ) in enemies.iter_mut()
+    {
+        if special.map_or(false, Special::is_attacking) {
+            continue;
+        }
+        let swims = swims.is_some();
+        let position = transform.translation.truncate();
+        let swim_speed = speed.0 * speed_multiplier;
+        let chase_speed = tuning.0.chase_speed * speed_multiplier;
+        let to_player = reach(position, player_position, swims);
+
+        let target_velocity = match state {
+            AiState::Patrol => match path {
+                Some(mut path) => {
+                    if reach(position, path.target(), swims).length() <= ARRIVE_DISTANCE {
+                        path.advance();
+                    }
+                    heading(reach(position, path.target(), swims), swim_speed)
+                }
+                None => {
+                    if position.x < borders.left {
+                        *direction = Direction::Right;
+                    } else if position.x > borders.right {
+                        *direction = Direction::Left;
+                    }
+                    Vec2::new(direction.sign() * swim_speed, 0.0)
+                }
+            },
+            AiState::Notice(_) | AiState::Bite => Vec2::ZERO,
+            AiState::Chase { .. } => {
+                let target = route.next(position, swims).unwrap_or(player_position);
+                heading(reach(position, target, swims), chase_speed)
+            }
+            AiState::Cooldown(_) => -to_player.normalize_or_zero() * swim_speed,
+            AiState::ReturnHome => {
+                let target = route.next(position, swims).unwrap_or(home.0);
+                heading(reach(position, target, swims), swim_speed)
+            }
+            AiState::Stunned(_) => {
+                let drag = (KNOCKBACK_DRAG * time.delta_seconds()).min(1.0);
+                velocity.linear.truncate() * (1.0 - drag)
+            }
+        };
+
+        match state {
+            // Keeps facing whoever hit it while knocked back
+            AiState::Stunned(_) => {}
+            AiState::Notice(_) | AiState::Bite => *direction = Direction::towards(to_player.x),
+            _ if target_velocity.x != 0.0 => *direction = Direction::towards(target_velocity.x),
+            _ => {}
+        }
+        velocity.linear.x = target_velocity.x;
+        if swims {
+            velocity.linear.y = target_velocity.y;
+        }
+
+        let clip = match (state, &*direction) {
+            (AiState::Bite, Direction::Left) => &animations.bite_left,
+            (AiState::Bite, Direction::Right) => &animations.bite_right,
+            (_, Direction::Left) => &animations.swim_left,
+            (_, Direction::Right) => &animations.swim_right,
+        };
+        switch_clip(&mut animation, clip);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tuning() -> AiDef {
+        AiDef {
+            sight_range: 100.0,
+            notice_time: 0.5,
+            chase_speed: 150.0,
+            memory: 2.0,
+            leash_range: 300.0,
+            cooldown_time: 1.0,
+        }
+    }
+
+    fn senses(sees_player: bool, at_home: bool) -> Senses {
+        Senses {
+            sees_player,
+            player_in_territory: true,
+            biting: false,
+            beyond_leash: false,
+            at_home,
+        }
+    }
+
+    fn noticing() -> AiState {
+        AiState::Notice(Timer::from_seconds(tuning().notice_time, false))
+    }
+
+    #[test]
+    fn patrol_notices_player_in_sight() {
+        let next = next_state(
+            &mut AiState::Patrol,
+            &senses(true, true),
+            &tuning(),
+            &Time::default(),
+        );
+
+        assert!(matches!(next, Some(AiState::Notice(_))));
+    }
+
+    #[test]
+    fn losing_sight_while_noticing_at_home_goes_back_to_patrol() {
+        let next = next_state(
+            &mut noticing(),
+            &senses(false, true),
+            &tuning(),
+            &Time::default(),
+        );
+
+        assert!(matches!(next, Some(AiState::Patrol)));
+    }
+
+    #[test]
+    fn losing_sight_while_noticing_away_from_home_returns_home() {
+        let next = next_state(
+            &mut noticing(),
+            &senses(false, false),
+            &tuning(),
+            &Time::default(),
+        );
+
+        assert!(matches!(next, Some(AiState::ReturnHome)));
+    }
+
+    #[test]
+    fn noticing_waits_while_player_stays_in_sight() {
+        let next = next_state(
+            &mut noticing(),
+            &senses(true, false),
+            &tuning(),
+            &Time::default(),
+        );
+
+        assert!(next.is_none());
+    }
+}
@@ -0,0 +1,155 @@
+//! Sprite sheets and their animation clips, defined in `assets/sprites.sheets.ron`.
+//! Atlases and animations are created once and shared by every entity that uses them.
+
+use crate::ron_asset::RonHandle;
+use benimator::SpriteSheetAnimation;
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Relative to assets directory
+pub const SHEETS_PATH: &str = "sprites.sheets.ron";
+
+pub const SHEET_PLAYER: &str = "player";
+pub const SHEET_STAR: &str = "star";
+pub const SHEET_BUBBLE: &str = "bubble";
+
+/// Sprite sheet cut into a grid of equally sized frames
+#[derive(Deserialize, Clone, Debug)]
+pub struct SheetDef {
+    pub texture: String,
+    pub frame_size: (f32, f32),
+    pub columns: usize,
+    pub rows: usize,
+    pub frame_duration_ms: u64,
+    /// First and last frame of every clip, inclusive
+    #[serde(default)]
+    pub clips: HashMap<String, (usize, usize)>,
+    /// Clips that stop on their last frame instead of repeating
+    #[serde(default)]
+    pub once: Vec<String>,
+}
+
+#[derive(Deserialize, TypeUuid)]
+#[uuid = "c2f57e09-4b1d-4a6e-93d8-0e6b2a7f5c34"]
+pub struct SheetDefs {
+    /// Sheets by name
+    pub sheets: HashMap<String, SheetDef>,
+}
+
+pub struct Sheet {
+    pub texture_atlas: Handle<TextureAtlas>,
+    clips: HashMap<String, Handle<SpriteSheetAnimation>>,
+}
+
+/// Every sheet with its atlas and clips, available once sheets are loaded
+pub struct AnimationRegistry(HashMap<String, Sheet>);
+
+impl AnimationRegistry {
+    pub fn sheet(&self, name: &str) -> Option<&Sheet> {
+        self.0.get(name)
+    }
+
+    /// Atlas of sheet `name`, a missing sheet is logged and draws nothing
+    pub fn texture_atlas(&self, name: &str) -> Handle<TextureAtlas> {
+        match self.sheet(name) {
+            Some(sheet) => sheet.texture_atlas.clone(),
+            None => {
+                warn!("No sprite sheet \"{}\" in {}", name, SHEETS_PATH);
+                Handle::default()
+            }
+        }
+    }
+
+    /// Clip `clip` of sheet `name`, a missing clip is logged and does not animate
+    pub fn clip(&self, name: &str, clip: &str) -> Handle<SpriteSheetAnimation> {
+        match self.sheet(name).and_then(|sheet| sheet.clips.get(clip)) {
+            Some(handle) => handle.clone(),
+            None => {
+                warn!("No clip \"{}\" in sprite sheet \"{}\"", clip, name);
+                Handle::default()
+            }
+        }
+    }
+}
+
+/// Plays `clip`, unless it already plays: setting the same handle again would
+/// restart the animation
+pub fn switch_clip(
+    animation: &mut Mut<Handle<SpriteSheetAnimation>>,
+    clip: &Handle<SpriteSheetAnimation>,
+) {
+    if **animation != *clip {
+        **animation = clip.clone();
+    }
+}
+
+pub fn start_loading(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(RonHandle::<SheetDefs>(asset_server.load(SHEETS_PATH)));
+}
+
+/// Creates atlases and animations of every sheet, again when the file changes
+pub fn build_registry(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    sheet_defs: Res<Assets<SheetDefs>>,
+    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+    mut animations: ResMut<Assets<SpriteSheetAnimation>>,
+    mut asset_events: EventReader<AssetEvent<SheetDefs>>,
+) {
+    for event in asset_events.iter() {
+        let handle = match event {
+            AssetEvent::Created { handle } | AssetEvent::Modified { handle } => handle,
+            AssetEvent::Removed { .. } => continue,
+        };
+        let defs = match sheet_defs.get(handle) {
+            Some(defs) => defs,
+            None => continue,
+        };
+
+        let sheets = defs
+            .sheets
+            .iter()
+            .map(|(name, def)| {
+                let sheet = build_sheet(def, &asset_server, &mut texture_atlases, &mut animations);
+                (name.clone(), sheet)
+            })
+            .collect();
+        commands.insert_resource(AnimationRegistry(sheets));
+        info!("Loaded {} sprite sheets", defs.sheets.len());
+    }
+}
+
+fn build_sheet(
+    def: &SheetDef,
+    asset_server: &Res<AssetServer>,
+    texture_atlases: &mut ResMut<Assets<TextureAtlas>>,
+    animations: &mut ResMut<Assets<SpriteSheetAnimation>>,
+) -> Sheet {
+    let texture_atlas = TextureAtlas::from_grid(
+        asset_server.load(def.texture.as_str()),
+        Vec2::new(def.frame_size.0, def.frame_size.1),
+        def.columns,
+        def.rows,
+    );
+
+    let frame_duration = Duration::from_millis(def.frame_duration_ms);
+    let clips = def
+        .clips
+        .iter()
+        .map(|(name, &(first, last))| {
+            let mut animation = SpriteSheetAnimation::from_range(first..=last, frame_duration);
+            if def.once.contains(name) {
+                animation = animation.once();
+            }
+            (name.clone(), animations.add(animation))
+        })
+        .collect();
+
+    Sheet {
+        texture_atlas: texture_atlases.add(texture_atlas),
+        clips,
+    }
+}
@@ -0,0 +1,89 @@
+//! Checks every TMX level without starting the game.
+//!
+//! Usage: `cargo run --bin levelcheck [LEVELS_DIR] [FISH_FILE]`, exits with non-zero code
+//! if any level has errors.
+
+use std::path::Path;
+use std::process::exit;
+use std::{env, fs};
+
+use bevy_game_jam::fish::{self, FishDefs};
+use bevy_game_jam::tmx::{self, LevelError, LevelErrorKind};
+
+const DEFAULT_LEVELS_DIR: &str = "assets/levels";
+const ASSETS_DIR: &str = "assets";
+
+fn main() {
+    let dir = env::args()
+        .nth(1)
+        .unwrap_or_else(|| DEFAULT_LEVELS_DIR.to_string());
+    let fish_file = env::args()
+        .nth(2)
+        .unwrap_or_else(|| format!("{}/{}", ASSETS_DIR, fish::FISH_PATH));
+
+    let defs = match fs::read(&fish_file).map(|bytes| FishDefs::parse(&bytes)) {
+        Ok(Ok(defs)) => defs,
+        Ok(Err(err)) => {
+            eprintln!("Invalid {}: {}", fish_file, err);
+            exit(2);
+        }
+        Err(err) => {
+            eprintln!("Cannot read {}: {}", fish_file, err);
+            exit(2);
+        }
+    };
+    let enemy_types = defs.names();
+    let walker_types = defs.walker_names();
+
+    let mut files: Vec<_> = match fs::read_dir(&dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().map_or(false, |ext| ext == "tmx"))
+            .collect(),
+        Err(err) => {
+            eprintln!("Cannot read {}: {}", dir, err);
+            exit(2);
+        }
+    };
+    files.sort();
+
+    let mut error_count = 0;
+    for path in &files {
+        let errors = check_level(path, &enemy_types, &walker_types);
+        if errors.is_empty() {
+            println!("ok    {}", path.display());
+        } else {
+            println!("FAIL  {}", path.display());
+            for error in &errors {
+                println!("      {}", error);
+            }
+        }
+        error_count += errors.len();
+    }
+
+    println!("{} levels checked, {} errors", files.len(), error_count);
+    if error_count > 0 {
+        exit(1);
+    }
+}
+
+fn check_level(path: &Path, enemy_types: &[String], walker_types: &[String]) -> Vec<LevelError> {
+    let file = path.display().to_string();
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(_) => return vec![LevelError::new(&file, LevelErrorKind::LoadFailed)],
+    };
+    let map = match tiled::parse(bytes.as_slice()) {
+        Ok(map) => map,
+        Err(err) => {
+            return vec![LevelError::new(
+                &file,
+                LevelErrorKind::Parse(err.to_string()),
+            )]
+        }
+    };
+
+    let mut errors = tmx::validate(&map, &file, enemy_types);
+    errors.extend(tmx::lint(&map, &file, enemy_types, walker_types));
+    errors
+}
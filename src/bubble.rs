@@ -1,3 +1,5 @@
+use crate::animation::{AnimationRegistry, SHEET_BUBBLE};
+use crate::properties::BubbleGeneratorProperties;
 use bevy::math::Affine2;
 use bevy::prelude::*;
 
@@ -48,16 +50,10 @@ impl BubbleBundle {
 pub fn process_bubble_generators(
     mut commands: Commands,
     time: Res<Time>,
-    asset_server: Res<AssetServer>,
-    mut textures: ResMut<Assets<TextureAtlas>>,
+    registry: Res<AnimationRegistry>,
     mut bubble_generators_query: Query<(Entity, &mut BubbleGenerator)>,
 ) {
-    let texture_atlas = textures.add(TextureAtlas::from_grid(
-        asset_server.load("bubble.png"),
-        Vec2::new(8.0, 8.0),
-        2,
-        1,
-    ));
+    let texture_atlas = registry.texture_atlas(SHEET_BUBBLE);
 
     for (entity, mut bubble_generator) in bubble_generators_query.iter_mut() {
         bubble_generator.timer.tick(time.delta());
@@ -97,8 +93,15 @@ pub fn process_bubbles(
     }
 }
 
-pub fn spawn_bubble_generator(commands: &mut Commands, position: Vec2) {
-    let timer = Timer::from_seconds(rand::random::<f32>() * 4.0 + 3.0, true);
+pub fn spawn_bubble_generator(
+    commands: &mut Commands,
+    position: Vec2,
+    properties: &BubbleGeneratorProperties,
+) {
+    let interval = properties
+        .interval
+        .unwrap_or_else(|| rand::random::<f32>() * 4.0 + 3.0);
+    let timer = Timer::from_seconds(interval, true);
     commands.spawn_bundle((
         BubbleGenerator { timer },
         Transform {
@@ -0,0 +1,140 @@
+//! The crab fighting back: landing on fish and pinching them stuns them, or defeats
+//! them for score once they run out of health.
+
+use crate::advantage::{Advantage, PlayerAdvantage};
+use crate::ai::AiState;
+use crate::enemy::{Enemy, Health, Reward};
+use crate::input::{Action, ActionState};
+use crate::player::Player;
+use crate::{PlayerCollision, PlayerCollisionEventType};
+use bevy::math::const_vec2;
+use bevy::prelude::*;
+use heron::Velocity;
+
+/// Upward speed of the crab after landing on a fish
+const STOMP_BOUNCE: f32 = 450.0;
+/// Speed a hit fish is pushed away from the crab with
+const KNOCKBACK_SPEED: f32 = 250.0;
+const STUN_SECONDS: f32 = 1.5;
+/// Largest distance from the crab to the center of a fish its claws reach, on each axis.
+/// Crabs pinch with both claws at once, so fish on either side are hit.
+const PINCH_REACH: Vec2 = const_vec2!([40.0, 24.0]);
+const PINCH_COOLDOWN: f32 = 0.4;
+
+#[derive(Default)]
+pub struct Score(pub u32);
+
+/// Fish hit by a stomp or a pinch
+pub struct EnemyHit(pub Entity);
+
+/// Landing on top of a fish hits it and bounces the crab back up
+pub fn stomp(
+    mut player_collisions: EventReader<PlayerCollision>,
+    enemies: Query<(), With<Enemy>>,
+    mut player: Query<&mut Velocity, With<Player>>,
+    mut hits: EventWriter<EnemyHit>,
+) {
+    for collision in player_collisions.iter() {
+        let enemy = collision.other.rigid_body_entity();
+        // Sensors report no normals, so only touching the body of a fish counts
+        let landed = collision
+            .player
+            .normals()
+            .iter()
+            .any(|normal| normal.y >= 0.9);
+        if !matches!(collision.event_type, PlayerCollisionEventType::Started)
+            || !landed
+            || enemies.get(enemy).is_err()
+        {
+            continue;
+        }
+
+        if let Ok(mut velocity) = player.get_single_mut() {
+            velocity.linear.y = STOMP_BOUNCE;
+        }
+        hits.send(EnemyHit(enemy));
+    }
+}
+
+pub fn pinch(
+    time: Res<Time>,
+    actions: Res<ActionState>,
+    mut reload: Local<f32>,
+    player: Query<&Transform, With<Player>>,
+    enemies: Query<(Entity, &Transform), With<Enemy>>,
+    mut hits: EventWriter<EnemyHit>,
+) {
+    *reload = (*reload - time.delta_seconds()).max(0.0);
+    if !actions.just_pressed(Action::Pinch) || *reload > 0.0 {
+        return;
+    }
+    let player_position = match player.get_single() {
+        Ok(transform) => transform.translation.truncate(),
+        Err(_) => return,
+    };
+    *reload = PINCH_COOLDOWN;
+
+    for (enemy, transform) in enemies.iter() {
+        let offset = (transform.translation.truncate() - player_position).abs();
+        if offset.x <= PINCH_REACH.x && offset.y <= PINCH_REACH.y {
+            hits.send(EnemyHit(enemy));
+        }
+    }
+}
+
+/// Hurts, knocks back and stuns fish that were hit, defeated fish are removed
+#[allow(clippy::type_complexity)]
+pub fn apply_hits(
+    mut commands: Commands,
+    mut hits: EventReader<EnemyHit>,
+    advantage: Res<Advantage>,
+    mut score: ResMut<Score>,
+    player: Query<&Transform, With<Player>>,
+    mut enemies: Query<
+        (
+            &Transform,
+            &mut Health,
+            &mut AiState,
+            &mut Velocity,
+            &Reward,
+        ),
+        (With<Enemy>, Without<Player>),
+    >,
+) {
+    let player_position = match player.get_single() {
+        Ok(transform) => transform.translation.truncate(),
+        Err(_) => return,
+    };
+    let damage = if matches!(
+        advantage.as_ref(),
+        Advantage::Player(PlayerAdvantage::StrongClaws)
+    ) {
+        2
+    } else {
+        1
+    };
+
+    for EnemyHit(enemy) in hits.iter() {
+        let (transform, mut health, mut state, mut velocity, reward) = match enemies.get_mut(*enemy)
+        {
+            Ok(components) => components,
+            Err(_) => continue,
+        };
+        if health.0 == 0 {
+            // Stomped and pinched in the same frame, already defeated
+            continue;
+        }
+
+        health.0 = health.0.saturating_sub(damage);
+        if health.0 == 0 {
+            score.0 += reward.0;
+            info!("Fish defeated, score {}", score.0);
+            commands.entity(*enemy).despawn_recursive();
+            continue;
+        }
+
+        let away = (transform.translation.truncate() - player_position).normalize_or_zero();
+        velocity.linear = (away * KNOCKBACK_SPEED).extend(0.0);
+        *state = AiState::Stunned(Timer::from_seconds(STUN_SECONDS, false));
+    }
+}
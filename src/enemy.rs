@@ -1,24 +1,81 @@
-use super::Hit;
-use crate::advantage::{Advantage, EnemyAdvantage};
-use crate::player::Player;
+use crate::ai::{AiState, Home, PatrolPath, Route, Swims, Tuning};
+use crate::animation::AnimationRegistry;
+use crate::fish::{FishDef, FishDefs, Movement, FISH_PATH};
+use crate::properties::EnemyProperties;
+use crate::ron_asset::RonHandle;
+use crate::special::{Special, SpecialHitbox};
+use crate::tmx::Waypoints;
 use benimator::*;
 use bevy::prelude::*;
+use bevy::utils::HashMap;
 use heron::*;
-use std::time::Duration;
+
+const CLIP_SWIM_LEFT: &str = "swim_left";
+const CLIP_SWIM_RIGHT: &str = "swim_right";
+const CLIP_BITE_LEFT: &str = "bite_left";
+const CLIP_BITE_RIGHT: &str = "bite_right";
+const CLIP_SPECIAL_LEFT: &str = "special_left";
+const CLIP_SPECIAL_RIGHT: &str = "special_right";
 
 #[derive(Component)]
 pub struct Enemy;
 
+/// Name of the fish archetype, same as the TMX object type
 #[derive(Component)]
-pub enum FishType {
-    Anglerfish,
-    Sawfish,
-}
+pub struct FishType(pub String);
 
 #[derive(Component)]
 pub struct Borders {
-    left: f32,
-    right: f32,
+    pub left: f32,
+    pub right: f32,
+}
+
+/// Horizontal speed in pixels per second, before advantages
+#[derive(Component)]
+pub struct Speed(pub f32);
+
+/// Stomps or pinches left before the fish is defeated
+#[derive(Component)]
+pub struct Health(pub u8);
+
+/// Score for defeating the fish
+#[derive(Component)]
+pub struct Reward(pub u32);
+
+/// Bites the player every `cooldown` while touching them
+#[derive(Component)]
+pub struct Bite {
+    /// HP taken from the player by one bite, before advantages
+    pub strength: u8,
+    pub cooldown: Timer,
+    /// Shapes of this fish touching the player, hitbox and sensor report contacts separately
+    contacts: u8,
+}
+
+impl Bite {
+    pub fn new(strength: u8, cooldown: f32) -> Self {
+        Bite {
+            strength,
+            cooldown: Timer::from_seconds(cooldown, true),
+            contacts: 0,
+        }
+    }
+
+    pub fn start_contact(&mut self) {
+        // First bite comes a full cooldown after touching, not right away
+        if self.contacts == 0 {
+            self.cooldown.reset();
+        }
+        self.contacts += 1;
+    }
+
+    pub fn stop_contact(&mut self) {
+        self.contacts = self.contacts.saturating_sub(1);
+    }
+
+    pub fn is_biting(&self) -> bool {
+        self.contacts > 0
+    }
 }
 
 #[derive(Component)]
@@ -27,134 +84,128 @@ pub enum Direction {
     Right,
 }
 
+impl Direction {
+    /// Direction of a horizontal offset, zero counts as right
+    pub fn towards(dx: f32) -> Self {
+        if dx < 0.0 {
+            Direction::Left
+        } else {
+            Direction::Right
+        }
+    }
+
+    pub fn sign(&self) -> f32 {
+        match self {
+            Direction::Left => -1.0,
+            Direction::Right => 1.0,
+        }
+    }
+}
+
 #[derive(Component)]
 pub struct MainCamera;
 
-#[derive(Default)]
-pub struct Animations {
-    a_left: Handle<SpriteSheetAnimation>,
-    a_right: Handle<SpriteSheetAnimation>,
-    a_bite_left: Handle<SpriteSheetAnimation>,
-    a_bite_right: Handle<SpriteSheetAnimation>,
-    s_left: Handle<SpriteSheetAnimation>,
-    s_right: Handle<SpriteSheetAnimation>,
-    s_bite_left: Handle<SpriteSheetAnimation>,
-    s_bite_right: Handle<SpriteSheetAnimation>,
+/// Animations of one enemy, shared by all fish of its archetype
+#[derive(Component, Clone)]
+pub struct FishAnimations {
+    pub swim_left: Handle<SpriteSheetAnimation>,
+    pub swim_right: Handle<SpriteSheetAnimation>,
+    pub bite_left: Handle<SpriteSheetAnimation>,
+    pub bite_right: Handle<SpriteSheetAnimation>,
+    /// Default handles for fish without a special attack
+    pub special_left: Handle<SpriteSheetAnimation>,
+    pub special_right: Handle<SpriteSheetAnimation>,
 }
 
-pub fn spawn_anglerfish(
-    commands: &mut Commands,
-    asset_server: &Res<AssetServer>,
-    texture_atlases: &mut ResMut<Assets<TextureAtlas>>,
-    animations: &mut ResMut<Assets<SpriteSheetAnimation>>,
-    handles: &mut ResMut<Animations>,
-    position: Vec2,
-) {
-    let texture = asset_server.load("enemy.png");
-    let texture_atlas = TextureAtlas::from_grid(texture, Vec2::new(64.0, 64.0), 22, 1);
-    let texture_atlas_handle = texture_atlases.add(texture_atlas);
+pub struct Archetype {
+    pub def: FishDef,
+    texture_atlas: Handle<TextureAtlas>,
+    animations: FishAnimations,
+}
 
-    handles.a_left = animations.add(SpriteSheetAnimation::from_range(
-        6..=13,
-        Duration::from_millis(100),
-    ));
+/// Fish archetypes by TMX object type, available once definitions are loaded
+pub struct Archetypes(pub HashMap<String, Archetype>);
 
-    handles.a_right = animations.add(SpriteSheetAnimation::from_range(
-        14..=21,
-        Duration::from_millis(100),
-    ));
+impl Archetypes {
+    /// Object types that spawn enemies
+    pub fn types(&self) -> Vec<String> {
+        self.0.keys().cloned().collect()
+    }
 
-    handles.a_bite_left = animations.add(SpriteSheetAnimation::from_range(
-        0..=2,
-        Duration::from_millis(100),
-    ));
+    /// Object types of fish that walk along the floor
+    pub fn walker_types(&self) -> Vec<String> {
+        self.0
+            .iter()
+            .filter(|(_, archetype)| archetype.def.movement == Movement::Walk)
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+}
 
-    handles.a_bite_right = animations.add(SpriteSheetAnimation::from_range(
-        3..=5,
-        Duration::from_millis(100),
-    ));
+pub fn start_loading(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(RonHandle::<FishDefs>(asset_server.load(FISH_PATH)));
+}
 
-    commands
-        .spawn_bundle(SpriteSheetBundle {
-            texture_atlas: texture_atlas_handle.clone(),
-            sprite: TextureAtlasSprite {
-                index: 0,
-                ..Default::default()
-            },
-            transform: Transform {
-                translation: Vec3::new(position.x, position.y, 5.0),
-                ..Default::default()
-            },
-            ..Default::default()
-        })
-        .insert(Enemy)
-        .insert(Direction::Left)
-        .insert(RigidBody::Dynamic)
-        .with_children(|children| {
-            children.spawn_bundle((
-                CollisionShape::Cuboid {
-                    half_extends: Vec3::new(20.0 / 2.0, 40.0 / 2.0, 0.0),
-                    border_radius: None,
-                },
-                Transform::default(),
-                GlobalTransform::default(),
-            ));
+/// Looks up sheets of every archetype, again when either file changes
+pub fn build_archetypes(
+    mut commands: Commands,
+    fish_defs_handle: Res<RonHandle<FishDefs>>,
+    fish_defs: Res<Assets<FishDefs>>,
+    registry: Option<Res<AnimationRegistry>>,
+    mut asset_events: EventReader<AssetEvent<FishDefs>>,
+) {
+    let defs_changed = asset_events
+        .iter()
+        .any(|event| !matches!(event, AssetEvent::Removed { .. }));
+    // Sheets can finish loading before or after the fish
+    let registry = match registry {
+        Some(registry) if defs_changed || registry.is_changed() => registry,
+        _ => return,
+    };
+    let defs = match fish_defs.get(&fish_defs_handle.0) {
+        Some(defs) => defs,
+        None => return,
+    };
 
-            children.spawn_bundle((
-                SensorShape,
-                CollisionShape::Cuboid {
-                    half_extends: Vec3::new(80.0 / 2.0, 40.0 / 2.0, 0.0),
-                    border_radius: None,
+    let archetypes = defs
+        .fish
+        .iter()
+        .map(|def| {
+            let special_clip = |clip| match def.special {
+                Some(_) => registry.clip(&def.sheet, clip),
+                None => Handle::default(),
+            };
+            let archetype = Archetype {
+                def: def.clone(),
+                texture_atlas: registry.texture_atlas(&def.sheet),
+                animations: FishAnimations {
+                    swim_left: registry.clip(&def.sheet, CLIP_SWIM_LEFT),
+                    swim_right: registry.clip(&def.sheet, CLIP_SWIM_RIGHT),
+                    bite_left: registry.clip(&def.sheet, CLIP_BITE_LEFT),
+                    bite_right: registry.clip(&def.sheet, CLIP_BITE_RIGHT),
+                    special_left: special_clip(CLIP_SPECIAL_LEFT),
+                    special_right: special_clip(CLIP_SPECIAL_RIGHT),
                 },
-            ));
+            };
+            (def.name.clone(), archetype)
         })
-        .insert(Velocity::from(Vec3::new(0.0, 0.0, 0.0)))
-        .insert(RotationConstraints::lock())
-        .insert(PhysicMaterial {
-            restitution: 0.2,
-            ..Default::default()
-        })
-        .insert(handles.a_left.clone())
-        .insert(Play)
-        .insert(Borders{left: position.x - 70.0, right: position.x + 70.0})
-        .insert(FishType::Anglerfish);
+        .collect();
+    commands.insert_resource(Archetypes(archetypes));
+    info!("Loaded {} fish archetypes", defs.fish.len());
 }
 
-pub fn spawn_sawfish(
+pub fn spawn(
     commands: &mut Commands,
-    asset_server: &Res<AssetServer>,
-    texture_atlases: &mut ResMut<Assets<TextureAtlas>>,
-    animations: &mut ResMut<Assets<SpriteSheetAnimation>>,
-    handles: &mut ResMut<Animations>,
+    archetype: &Archetype,
     position: Vec2,
+    properties: &EnemyProperties,
+    waypoints: Option<Waypoints>,
 ) {
-    let texture = asset_server.load("enemy2.png");
-    let texture_atlas = TextureAtlas::from_grid(texture, Vec2::new(96.0, 48.0), 12, 1);
-    let texture_atlas_handle = texture_atlases.add(texture_atlas);
-
-    handles.s_left = animations.add(SpriteSheetAnimation::from_range(
-        4..=7,
-        Duration::from_millis(100),
-    ));
-
-    handles.s_right = animations.add(SpriteSheetAnimation::from_range(
-        8..=11,
-        Duration::from_millis(100),
-    ));
-
-    handles.s_bite_left = animations.add(SpriteSheetAnimation::from_range(
-        0..=1,
-        Duration::from_millis(100),
-    ));
+    let def = &archetype.def;
 
-    handles.s_bite_right = animations.add(SpriteSheetAnimation::from_range(
-        2..=3,
-        Duration::from_millis(100),
-    ));
-
-    commands
+    let enemy = commands
         .spawn_bundle(SpriteSheetBundle {
-            texture_atlas: texture_atlas_handle.clone(),
+            texture_atlas: archetype.texture_atlas.clone(),
             sprite: TextureAtlasSprite {
                 index: 0,
                 ..Default::default()
@@ -171,7 +222,7 @@ pub fn spawn_sawfish(
         .with_children(|children| {
             children.spawn_bundle((
                 CollisionShape::Cuboid {
-                    half_extends: Vec3::new(20.0 / 2.0, 40.0 / 2.0, 0.0),
+                    half_extends: Vec3::new(def.hitbox.0 / 2.0, def.hitbox.1 / 2.0, 0.0),
                     border_radius: None,
                 },
                 Transform::default(),
@@ -181,10 +232,25 @@ pub fn spawn_sawfish(
             children.spawn_bundle((
                 SensorShape,
                 CollisionShape::Cuboid {
-                    half_extends: Vec3::new(80.0 / 2.0, 40.0 / 2.0, 0.0),
+                    half_extends: Vec3::new(def.sensor.0 / 2.0, def.sensor.1 / 2.0, 0.0),
                     border_radius: None,
                 },
             ));
+
+            if let Some(special) = &def.special {
+                children.spawn_bundle((
+                    SensorShape,
+                    SpecialHitbox,
+                    CollisionShape::Cuboid {
+                        half_extends: Vec3::new(
+                            special.hitbox.0 / 2.0,
+                            special.hitbox.1 / 2.0,
+                            0.0,
+                        ),
+                        border_radius: None,
+                    },
+                ));
+            }
         })
         .insert(Velocity::from(Vec3::new(0.0, 0.0, 0.0)))
         .insert(RotationConstraints::lock())
@@ -192,94 +258,39 @@ pub fn spawn_sawfish(
             restitution: 0.2,
             ..Default::default()
         })
-        .insert(handles.s_left.clone())
+        .insert(archetype.animations.swim_left.clone())
+        .insert(archetype.animations.clone())
         .insert(Play)
-        .insert(Borders{left: position.x - 70.0, right: position.x + 70.0})
-        .insert(FishType::Sawfish);
-}
-
-pub fn r#move(
-    mut enemy: Query<
-        (
-            &Transform,
-            &mut Velocity,
-            &mut Direction,
-            &mut Handle<SpriteSheetAnimation>,
-            &FishType,
-            &Borders,
-        ),
-        With<Enemy>,
-    >,
-    player: Query<&Transform, With<Player>>,
-    animations: Res<Animations>,
-    hit: ResMut<Hit>,
-    adv: Res<Advantage>,
-) {
-    let player = player.single();
-    let enemy_speed = if matches!(
-        adv.as_ref(),
-        Advantage::Enemy(EnemyAdvantage::DoubleSpeed)
-    ) {
-        170.0
-    } else {
-        100.0
-    };
-
-    for (enemy_transform, mut enemy_vel, mut direction, mut animation, fishtype, borders) in enemy.iter_mut() {
-        match *direction {
-            Direction::Left => enemy_vel.linear[0] = -enemy_speed,
-            Direction::Right => enemy_vel.linear[0] = enemy_speed,
-        }
+        .insert(Borders {
+            left: position.x - properties.patrol_range,
+            right: position.x + properties.patrol_range,
+        })
+        .insert(Speed(properties.speed))
+        .insert(Bite::new(properties.bite_strength, def.bite_cooldown))
+        .insert(Health(def.hp))
+        .insert(Reward(def.score))
+        .insert(FishType(def.name.clone()))
+        .insert(AiState::Patrol)
+        .insert(Home(position))
+        .insert(Route::default())
+        .insert(Tuning(def.ai.clone()))
+        .id();
 
-        if player.translation.y - enemy_transform.translation.y > -70.0
-            && player.translation.y - enemy_transform.translation.y < 70.0
-        {
-            match (player.translation.x - enemy_transform.translation.x) as i32 {
-                -50..=0 if hit.0 => {
-                    match *fishtype {
-                        FishType::Anglerfish => *animation = animations.a_bite_left.clone(),
-                        FishType::Sawfish => *animation = animations.s_bite_left.clone(),
-                    }
-                    *direction = Direction::Left
-                }
-                1..=50 if hit.0 => {
-                    match *fishtype {
-                        FishType::Anglerfish => *animation = animations.a_bite_right.clone(),
-                        FishType::Sawfish => *animation = animations.s_bite_right.clone(),
-                    }
-                    *direction = Direction::Right
-                }
-                -70..=0 => {
-                    match *fishtype {
-                        FishType::Anglerfish => *animation = animations.a_left.clone(),
-                        FishType::Sawfish => *animation = animations.s_left.clone(),
-                    }
-                    *direction = Direction::Left
-                }
-                1..=70 => {
-                    match *fishtype {
-                        FishType::Anglerfish => *animation = animations.a_right.clone(),
-                        FishType::Sawfish => *animation = animations.s_right.clone(),
-                    }
-                    *direction = Direction::Right
-                }
-                _ => (),
-            }
-        }
-        
-        if enemy_transform.translation.x < borders.left {
-            match *fishtype {
-                FishType::Anglerfish => *animation = animations.a_right.clone(),
-                FishType::Sawfish => *animation = animations.s_right.clone(),
-            }
-            
-            *direction = Direction::Right;
-        } else if enemy_transform.translation.x > borders.right {
-            match *fishtype {
-                FishType::Anglerfish => *animation = animations.a_left.clone(),
-                FishType::Sawfish => *animation = animations.s_left.clone(),
-            }
-            *direction = Direction::Left;
-        }
+    if def.movement == Movement::Swim {
+        // Buoyancy cancels out gravity, so the fish hovers wherever it stops
+        commands
+            .entity(enemy)
+            .insert(Swims)
+            .insert(Acceleration::from_linear(Vec3::new(
+                0.0,
+                -crate::GRAVITY,
+                0.0,
+            )));
+    }
+    if let Some(waypoints) = waypoints {
+        commands.entity(enemy).insert(PatrolPath::new(waypoints));
+    }
+    if let Some(special) = &def.special {
+        commands.entity(enemy).insert(Special::new(special.clone()));
     }
 }
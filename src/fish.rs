@@ -0,0 +1,191 @@
+//! Enemy fish archetypes, defined in `assets/enemies.fish.ron`. Does not touch the ECS world,
+//! so level checks can tell which TMX object types are fish.
+
+use crate::properties::EnemyProperties;
+use bevy::reflect::TypeUuid;
+use serde::Deserialize;
+use std::fmt;
+
+/// Relative to assets directory
+pub const FISH_PATH: &str = "enemies.fish.ron";
+
+#[derive(Deserialize, TypeUuid)]
+#[uuid = "8d4e7b1a-2c69-4f03-b5de-61a9c0f3e872"]
+pub struct FishDefs {
+    pub fish: Vec<FishDef>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct FishDef {
+    /// Object type used for this fish in Tiled
+    pub name: String,
+    /// Sprite sheet with `swim_left`, `swim_right`, `bite_left` and `bite_right` clips,
+    /// and `special_left` and `special_right` for fish with a special attack
+    pub sheet: String,
+    /// Size of the solid body, in pixels
+    pub hitbox: (f32, f32),
+    /// Size of the area where the fish bites, in pixels
+    pub sensor: (f32, f32),
+    pub speed: f32,
+    pub bite_strength: u8,
+    /// Seconds between bites while touching the player
+    pub bite_cooldown: f32,
+    pub patrol_range: f32,
+    /// Stomps or pinches it takes to defeat the fish
+    pub hp: u8,
+    /// Added to the score when the fish is defeated
+    pub score: u32,
+    #[serde(default)]
+    pub movement: Movement,
+    pub ai: AiDef,
+    #[serde(default)]
+    pub special: Option<SpecialDef>,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum Movement {
+    /// Falls to the floor and moves along the x axis only
+    Walk,
+    /// Floats in open water and moves on both axes
+    Swim,
+}
+
+impl Default for Movement {
+    fn default() -> Self {
+        Movement::Walk
+    }
+}
+
+/// How a fish hunts, see `ai::AiState` for the states these tune
+#[derive(Deserialize, Clone, Debug)]
+pub struct AiDef {
+    /// How far the fish sees the player, in pixels
+    pub sight_range: f32,
+    /// Seconds between seeing the player and starting the chase
+    pub notice_time: f32,
+    /// Speed while chasing, in pixels per second
+    pub chase_speed: f32,
+    /// Seconds the fish keeps chasing after losing sight of the player
+    pub memory: f32,
+    /// How far from its home the fish follows the player, in pixels
+    pub leash_range: f32,
+    /// Seconds the fish backs off after the player escapes a bite
+    pub cooldown_time: f32,
+}
+
+/// Attack a chasing fish makes on its own cooldown, besides biting
+#[derive(Deserialize, Clone, Debug)]
+pub struct SpecialDef {
+    pub attack: Attack,
+    /// Distance to the player the fish attacks from, in pixels
+    pub range: f32,
+    /// Seconds the fish telegraphs the attack before it starts
+    pub windup: f32,
+    /// Seconds the attack lasts
+    pub duration: f32,
+    /// Seconds after the attack before the next one
+    pub cooldown: f32,
+    /// Size of the area where the attack reaches the player, in pixels
+    pub hitbox: (f32, f32),
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub enum Attack {
+    /// Pulls the player towards the fish, in pixels per second squared
+    Lure { pull: f32 },
+    /// Charges along the x axis at `speed`, taking `damage` HP from the player it hits
+    Dash { speed: f32, damage: u8 },
+}
+
+/// Fish file that cannot be read, or has values the game cannot run with
+#[derive(Debug)]
+pub enum FishError {
+    Parse(ron::Error),
+    OutOfRange {
+        fish: String,
+        field: &'static str,
+        expected: &'static str,
+    },
+}
+
+impl fmt::Display for FishError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FishError::Parse(err) => write!(f, "{}", err),
+            FishError::OutOfRange {
+                fish,
+                field,
+                expected,
+            } => write!(f, "{} of fish \"{}\" must be {}", field, fish, expected),
+        }
+    }
+}
+
+impl std::error::Error for FishError {}
+
+impl FishDefs {
+    pub fn parse(bytes: &[u8]) -> Result<Self, FishError> {
+        let defs: FishDefs = ron::de::from_bytes(bytes).map_err(FishError::Parse)?;
+        defs.check()?;
+        Ok(defs)
+    }
+
+    /// Checks that every fish has values the game can run with
+    pub fn check(&self) -> Result<(), FishError> {
+        for fish in &self.fish {
+            fish.check()?;
+        }
+        Ok(())
+    }
+
+    /// Object types that spawn enemies
+    pub fn names(&self) -> Vec<String> {
+        self.fish.iter().map(|fish| fish.name.clone()).collect()
+    }
+
+    /// Object types of fish that walk along the floor
+    pub fn walker_names(&self) -> Vec<String> {
+        self.fish
+            .iter()
+            .filter(|fish| fish.movement == Movement::Walk)
+            .map(|fish| fish.name.clone())
+            .collect()
+    }
+}
+
+impl FishDef {
+    /// Checks values that end up in timers, which panic when they are negative
+    fn check(&self) -> Result<(), FishError> {
+        let out_of_range = |field, expected| FishError::OutOfRange {
+            fish: self.name.clone(),
+            field,
+            expected,
+        };
+        // Bites repeat on this timer, which cannot repeat without a duration
+        if self.bite_cooldown <= 0.0 {
+            return Err(out_of_range("bite_cooldown", "more than 0"));
+        }
+        if let Some(special) = &self.special {
+            let timers = [
+                ("special.windup", special.windup),
+                ("special.duration", special.duration),
+                ("special.cooldown", special.cooldown),
+            ];
+            for (field, seconds) in timers {
+                if seconds < 0.0 {
+                    return Err(out_of_range(field, "0 or more"));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Settings for enemies that do not override them in Tiled
+    pub fn properties(&self) -> EnemyProperties {
+        EnemyProperties {
+            patrol_range: self.patrol_range,
+            speed: self.speed,
+            bite_strength: self.bite_strength,
+        }
+    }
+}
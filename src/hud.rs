@@ -2,20 +2,35 @@ use bevy::{core::Stopwatch, ecs::system::OptionResState, prelude::*};
 
 use crate::{
     advantage::{Advantage, EnemyAdvantage, PlayerAdvantage},
+    combat::Score,
+    tilemap::Map,
+    water::Oxygen,
     Hp,
 };
 
 #[derive(Component)]
 pub struct HpLabel;
 
+#[derive(Component)]
+pub struct OxygenLabel;
+
+#[derive(Component)]
+pub struct ScoreLabel;
+
 #[derive(Component)]
 pub struct AdvantageLabel;
 
 #[derive(Component)]
 pub struct HintLabel;
 
+#[derive(Component)]
+pub struct LevelLabel;
+
 const HINT: &str = r#"
-WASD to move and jump.
+WASD or arrows to move and jump, or a gamepad.
+Land on fish or pinch them with Space.
+Jump to swim up in water, surface before your air runs out.
+P to pause and change controls.
 Each level has a random Unfair Advantage
 for you or your enemies.
 Take use of your advantage to gain resources.
@@ -58,6 +73,25 @@ pub fn spawn_hud(mut commands: Commands, asset_server: Res<AssetServer>) {
                 ..Default::default()
             });
 
+            parent
+                .spawn_bundle(TextBundle {
+                    style: Style {
+                        margin: Rect::all(Val::Px(5.0)),
+                        ..Default::default()
+                    },
+                    text: Text::with_section(
+                        "",
+                        TextStyle {
+                            font: asset_server.load("PublicPixel-0W6DP.ttf"),
+                            font_size: 30.0,
+                            color: Color::WHITE,
+                        },
+                        Default::default(),
+                    ),
+                    ..Default::default()
+                })
+                .insert(LevelLabel);
+
             parent
                 .spawn_bundle(TextBundle {
                     style: Style {
@@ -95,6 +129,44 @@ pub fn spawn_hud(mut commands: Commands, asset_server: Res<AssetServer>) {
                     ..Default::default()
                 })
                 .insert(HpLabel);
+
+            parent
+                .spawn_bundle(TextBundle {
+                    style: Style {
+                        margin: Rect::all(Val::Px(5.0)),
+                        ..Default::default()
+                    },
+                    text: Text::with_section(
+                        "",
+                        TextStyle {
+                            font: asset_server.load("PublicPixel-0W6DP.ttf"),
+                            font_size: 30.0,
+                            color: Color::WHITE,
+                        },
+                        Default::default(),
+                    ),
+                    ..Default::default()
+                })
+                .insert(OxygenLabel);
+
+            parent
+                .spawn_bundle(TextBundle {
+                    style: Style {
+                        margin: Rect::all(Val::Px(5.0)),
+                        ..Default::default()
+                    },
+                    text: Text::with_section(
+                        "score",
+                        TextStyle {
+                            font: asset_server.load("PublicPixel-0W6DP.ttf"),
+                            font_size: 30.0,
+                            color: Color::WHITE,
+                        },
+                        Default::default(),
+                    ),
+                    ..Default::default()
+                })
+                .insert(ScoreLabel);
         });
 }
 
@@ -107,6 +179,30 @@ pub fn update_hp_meter(mut hp_label: Query<&mut Text, With<HpLabel>>, hp: Res<Hp
     };
 }
 
+/// Shows the air left while it is not full
+pub fn update_oxygen_meter(mut label: Query<&mut Text, With<OxygenLabel>>, oxygen: Res<Oxygen>) {
+    if !oxygen.is_changed() {
+        return;
+    }
+    let section = &mut label.single_mut().sections[0];
+    section.value = if oxygen.0 < 1.0 {
+        format!("air {}%", (oxygen.0 * 100.0).ceil())
+    } else {
+        String::new()
+    };
+    section.style.color = if oxygen.0 < 0.25 {
+        Color::RED
+    } else {
+        Color::AQUAMARINE
+    };
+}
+
+pub fn update_score(mut score_label: Query<&mut Text, With<ScoreLabel>>, score: Res<Score>) {
+    if score.is_changed() {
+        score_label.single_mut().sections[0].value = format!("score {}", score.0);
+    }
+}
+
 pub fn update_advantage(mut label: Query<&mut Text, With<AdvantageLabel>>, adv: Res<Advantage>) {
     let section = &mut label.single_mut().sections[0];
     let (color, text) = match adv.into_inner() {
@@ -115,6 +211,7 @@ pub fn update_advantage(mut label: Query<&mut Text, With<AdvantageLabel>>, adv:
             match pa {
                 PlayerAdvantage::DoubleJump => "Double Jump",
                 PlayerAdvantage::DoubleInitialHp => "Double HP",
+                PlayerAdvantage::StrongClaws => "Strong claws",
             },
         ),
         Advantage::Enemy(ea) => (
@@ -129,6 +226,12 @@ pub fn update_advantage(mut label: Query<&mut Text, With<AdvantageLabel>>, adv:
     section.value = text.to_string();
 }
 
+pub fn update_level_name(mut label: Query<&mut Text, With<LevelLabel>>, map: Query<&Map>) {
+    if let Ok(map) = map.get_single() {
+        label.single_mut().sections[0].value = map.name.clone();
+    }
+}
+
 pub fn spawn_hint(parent: &mut ChildBuilder, asset_server: &Res<AssetServer>) {
     parent
         .spawn_bundle(TextBundle {
@@ -0,0 +1,377 @@
+//! Actions the player takes, bound to keys, gamepad buttons and gamepad sticks.
+//! Bindings are read from `bindings.ron` in the working directory and saved there
+//! when changed in the pause menu. The web build always starts with the defaults.
+
+use bevy::input::gamepad::{
+    Gamepad, GamepadAxis, GamepadAxisType, GamepadButton, GamepadButtonType, Gamepads,
+};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+
+pub const BINDINGS_PATH: &str = "bindings.ron";
+
+/// How far a stick has to be pushed to count as pressed
+const DEAD_ZONE: f32 = 0.5;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Action {
+    MoveLeft,
+    MoveRight,
+    Jump,
+    Dive,
+    Pinch,
+    Pause,
+    MenuUp,
+    MenuDown,
+    MenuSelect,
+}
+
+/// Every action, in the order the pause menu lists them
+pub const ACTIONS: [Action; 6] = [
+    Action::MoveLeft,
+    Action::MoveRight,
+    Action::Jump,
+    Action::Dive,
+    Action::Pinch,
+    Action::Pause,
+];
+
+/// Actions that move through the pause menu, bound to the keyboard and gamepad
+/// alike so either can rebind. They are not rebindable themselves.
+pub const MENU_ACTIONS: [Action; 3] = [Action::MenuUp, Action::MenuDown, Action::MenuSelect];
+
+impl Action {
+    fn is_menu(self) -> bool {
+        MENU_ACTIONS.contains(&self)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    Button(GamepadButtonType),
+    /// Stick pushed past the dead zone, towards positive or negative values
+    Axis {
+        axis: GamepadAxisType,
+        positive: bool,
+    },
+}
+
+impl Binding {
+    fn is_gamepad(&self) -> bool {
+        !matches!(self, Binding::Key(_))
+    }
+
+    fn is_pressed(
+        &self,
+        keys: &Input<KeyCode>,
+        buttons: &Input<GamepadButton>,
+        axes: &Axis<GamepadAxis>,
+        gamepads: &Gamepads,
+    ) -> bool {
+        match *self {
+            Binding::Key(key) => keys.pressed(key),
+            Binding::Button(button) => gamepads
+                .iter()
+                .any(|&gamepad| buttons.pressed(GamepadButton(gamepad, button))),
+            Binding::Axis { axis, positive } => gamepads.iter().any(|&gamepad| {
+                let value = axes.get(GamepadAxis(gamepad, axis)).unwrap_or(0.0);
+                if positive {
+                    value > DEAD_ZONE
+                } else {
+                    value < -DEAD_ZONE
+                }
+            }),
+        }
+    }
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Binding::Key(key) => write!(f, "{:?}", key),
+            Binding::Button(button) => write!(f, "pad {:?}", button),
+            Binding::Axis { axis, positive } => {
+                write!(f, "pad {:?}{}", axis, if *positive { "+" } else { "-" })
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Bindings(pub BTreeMap<Action, Vec<Binding>>);
+
+impl Default for Bindings {
+    fn default() -> Self {
+        use GamepadAxisType::*;
+        use GamepadButtonType::*;
+        let key = Binding::Key;
+        let button = Binding::Button;
+        let stick = |axis, positive| Binding::Axis { axis, positive };
+
+        Bindings(
+            [
+                (
+                    Action::MoveLeft,
+                    vec![
+                        key(KeyCode::A),
+                        key(KeyCode::Left),
+                        button(DPadLeft),
+                        stick(LeftStickX, false),
+                    ],
+                ),
+                (
+                    Action::MoveRight,
+                    vec![
+                        key(KeyCode::D),
+                        key(KeyCode::Right),
+                        button(DPadRight),
+                        stick(LeftStickX, true),
+                    ],
+                ),
+                (
+                    Action::Jump,
+                    vec![key(KeyCode::W), key(KeyCode::Up), button(South)],
+                ),
+                (
+                    Action::Dive,
+                    vec![
+                        key(KeyCode::S),
+                        key(KeyCode::Down),
+                        button(DPadDown),
+                        stick(LeftStickY, false),
+                    ],
+                ),
+                (Action::Pinch, vec![key(KeyCode::Space), button(West)]),
+                (Action::Pause, vec![key(KeyCode::P), button(Start)]),
+                (
+                    Action::MenuUp,
+                    vec![
+                        key(KeyCode::Up),
+                        key(KeyCode::W),
+                        button(DPadUp),
+                        stick(LeftStickY, true),
+                    ],
+                ),
+                (
+                    Action::MenuDown,
+                    vec![
+                        key(KeyCode::Down),
+                        key(KeyCode::S),
+                        button(DPadDown),
+                        stick(LeftStickY, false),
+                    ],
+                ),
+                (
+                    Action::MenuSelect,
+                    vec![key(KeyCode::Return), button(South)],
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        )
+    }
+}
+
+impl Bindings {
+    /// Saved bindings, or the defaults when there are none. Actions missing
+    /// from the file, like ones added after it was saved, keep their defaults.
+    pub fn load() -> Self {
+        let mut bindings = Bindings::default();
+        let saved: Option<Bindings> = read_file().and_then(|bytes| {
+            ron::de::from_bytes(&bytes)
+                .map_err(|err| warn!("Ignoring {}: {}", BINDINGS_PATH, err))
+                .ok()
+        });
+        if let Some(saved) = saved {
+            bindings.merge(saved);
+        }
+        bindings
+    }
+
+    fn merge(&mut self, saved: Bindings) {
+        self.0.extend(saved.0);
+    }
+
+    pub fn save(&self) {
+        let result = ron::ser::to_string_pretty(self, Default::default())
+            .map_err(|err| err.to_string())
+            .and_then(|text| write_file(&text).map_err(|err| err.to_string()));
+        match result {
+            Ok(()) => info!("Saved bindings to {}", BINDINGS_PATH),
+            Err(err) => warn!("Could not save {}: {}", BINDINGS_PATH, err),
+        }
+    }
+
+    pub fn of(&self, action: Action) -> &[Binding] {
+        self.0.get(&action).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Binds `binding` to `action` instead of its first binding of the same device,
+    /// keyboard or gamepad, and takes it away from any other action. Menu actions keep
+    /// their bindings, they share them with the actions played with.
+    pub fn rebind(&mut self, action: Action, binding: Binding) {
+        for (_, bindings) in self.0.iter_mut().filter(|(other, _)| !other.is_menu()) {
+            bindings.retain(|other| *other != binding);
+        }
+        let bindings = self.0.entry(action).or_default();
+        match bindings
+            .iter()
+            .position(|other| other.is_gamepad() == binding.is_gamepad())
+        {
+            Some(index) => bindings[index] = binding,
+            None => bindings.push(binding),
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn read_file() -> Option<Vec<u8>> {
+    std::fs::read(BINDINGS_PATH).ok()
+}
+
+#[cfg(target_arch = "wasm32")]
+fn read_file() -> Option<Vec<u8>> {
+    None
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn write_file(text: &str) -> std::io::Result<()> {
+    std::fs::write(BINDINGS_PATH, text)
+}
+
+#[cfg(target_arch = "wasm32")]
+fn write_file(_text: &str) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// Actions held and started this frame, through any of their bindings
+#[derive(Default)]
+pub struct ActionState {
+    pressed: HashSet<Action>,
+    just_pressed: HashSet<Action>,
+}
+
+impl ActionState {
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.just_pressed.contains(&action)
+    }
+}
+
+pub fn update_actions(
+    bindings: Res<Bindings>,
+    keys: Res<Input<KeyCode>>,
+    buttons: Res<Input<GamepadButton>>,
+    axes: Res<Axis<GamepadAxis>>,
+    gamepads: Res<Gamepads>,
+    mut state: ResMut<ActionState>,
+) {
+    let pressed: HashSet<Action> = ACTIONS
+        .into_iter()
+        .chain(MENU_ACTIONS)
+        .filter(|&action| {
+            bindings
+                .of(action)
+                .iter()
+                .any(|binding| binding.is_pressed(&keys, &buttons, &axes, &gamepads))
+        })
+        .collect();
+    state.just_pressed = pressed.difference(&state.pressed).copied().collect();
+    state.pressed = pressed;
+}
+
+/// First key or button pressed this frame, or stick pushed past the dead zone, for rebinding
+pub fn just_pressed_binding(
+    keys: &Input<KeyCode>,
+    buttons: &Input<GamepadButton>,
+    axes: &Axis<GamepadAxis>,
+    gamepads: &Gamepads,
+) -> Option<Binding> {
+    if let Some(&key) = keys.get_just_pressed().next() {
+        return Some(Binding::Key(key));
+    }
+    if let Some(&GamepadButton(_, button)) = buttons.get_just_pressed().next() {
+        return Some(Binding::Button(button));
+    }
+    let stick_axes = [
+        GamepadAxisType::LeftStickX,
+        GamepadAxisType::LeftStickY,
+        GamepadAxisType::RightStickX,
+        GamepadAxisType::RightStickY,
+    ];
+    gamepads.iter().find_map(|&gamepad: &Gamepad| {
+        stick_axes.into_iter().find_map(|axis| {
+            let value = axes.get(GamepadAxis(gamepad, axis)).unwrap_or(0.0);
+            (value.abs() > DEAD_ZONE).then(|| Binding::Axis {
+                axis,
+                positive: value > 0.0,
+            })
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use GamepadButtonType::*;
+
+    #[test]
+    fn rebind_replaces_binding_of_same_device() {
+        let mut bindings = Bindings::default();
+        bindings.rebind(Action::Pinch, Binding::Key(KeyCode::J));
+        assert_eq!(
+            bindings.of(Action::Pinch),
+            [Binding::Key(KeyCode::J), Binding::Button(West)]
+        );
+        bindings.rebind(Action::Pinch, Binding::Button(East));
+        assert_eq!(
+            bindings.of(Action::Pinch),
+            [Binding::Key(KeyCode::J), Binding::Button(East)]
+        );
+    }
+
+    #[test]
+    fn rebind_adds_binding_of_missing_device() {
+        let mut bindings = Bindings(BTreeMap::new());
+        bindings.rebind(Action::Pinch, Binding::Button(East));
+        bindings.rebind(Action::Pinch, Binding::Key(KeyCode::J));
+        assert_eq!(
+            bindings.of(Action::Pinch),
+            [Binding::Button(East), Binding::Key(KeyCode::J)]
+        );
+    }
+
+    #[test]
+    fn rebind_takes_binding_from_other_actions_but_not_menu() {
+        let mut bindings = Bindings::default();
+        bindings.rebind(Action::Pinch, Binding::Key(KeyCode::W));
+        assert_eq!(
+            bindings.of(Action::Jump),
+            [Binding::Key(KeyCode::Up), Binding::Button(South)]
+        );
+        assert!(bindings
+            .of(Action::MenuUp)
+            .contains(&Binding::Key(KeyCode::W)));
+    }
+
+    #[test]
+    fn saved_bindings_replace_defaults_of_their_actions_only() {
+        let saved: Bindings = ron::de::from_str("({Jump: [Key(Space)]})").unwrap();
+        let mut bindings = Bindings::default();
+        bindings.merge(saved);
+
+        let defaults = Bindings::default();
+        assert_eq!(bindings.of(Action::Jump), [Binding::Key(KeyCode::Space)]);
+        for action in ACTIONS.into_iter().chain(MENU_ACTIONS) {
+            if action != Action::Jump {
+                assert_eq!(bindings.of(action), defaults.of(action));
+            }
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use crate::advantage::Advantage;
+use crate::animation::{SheetDefs, SHEETS_PATH};
+use crate::enemy::Archetypes;
+use crate::fish::{FishDefs, FISH_PATH};
+use crate::ron_asset::RonHandle;
+use crate::tilemap::{show_broken_level, ChangeMap};
+use crate::tmx::{LevelError, LevelErrorKind};
+use crate::AppState;
+use bevy::asset::{AssetLoader, LoadContext, LoadState, LoadedAsset};
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::utils::BoxedFuture;
+use serde::Deserialize;
+
+const MANIFEST_PATH: &str = "levels/main.levels.ron";
+
+/// Level order as listed in `assets/levels/*.levels.ron`
+#[derive(Deserialize, TypeUuid)]
+#[uuid = "3f0c6a52-8f0e-4d55-9a57-2b1f4c7e9d13"]
+pub struct LevelManifest {
+    pub levels: Vec<LevelInfo>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct LevelInfo {
+    /// Path to TMX file, relative to assets directory
+    pub file: String,
+    pub name: String,
+    /// Advantages that can be rolled on this level, any advantage if empty
+    #[serde(default)]
+    pub advantages: Vec<Advantage>,
+}
+
+/// Raw contents of a TMX file, parsed when the map is spawned
+#[derive(TypeUuid)]
+#[uuid = "b5a1e2d4-61c7-4a8e-8e0b-7d2f9c3a5e41"]
+pub struct TmxMap {
+    pub bytes: Vec<u8>,
+}
+
+pub struct Level {
+    pub info: LevelInfo,
+    pub map: Handle<TmxMap>,
+}
+
+/// Levels in the order they are played, available once loading is done
+pub struct Levels(pub Vec<Level>);
+
+#[derive(Default)]
+pub struct TmxLoader;
+
+impl AssetLoader for TmxLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            load_context.set_default_asset(LoadedAsset::new(TmxMap {
+                bytes: bytes.to_vec(),
+            }));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["tmx"]
+    }
+}
+
+pub fn start_loading(mut commands: Commands, asset_server: Res<AssetServer>) {
+    // Lets designers edit TMX files while the game is running
+    asset_server.watch_for_changes().unwrap();
+    commands.insert_resource(RonHandle::<LevelManifest>(asset_server.load(MANIFEST_PATH)));
+}
+
+/// Waits for manifest, then for every level it lists, sprite sheets and fish archetypes,
+/// then starts the game
+#[allow(clippy::too_many_arguments)]
+pub fn wait_for_levels(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    manifest_handle: Res<RonHandle<LevelManifest>>,
+    manifests: Res<Assets<LevelManifest>>,
+    tmx_maps: Res<Assets<TmxMap>>,
+    levels: Option<Res<Levels>>,
+    sheet_defs_handle: Res<RonHandle<SheetDefs>>,
+    fish_defs_handle: Res<RonHandle<FishDefs>>,
+    archetypes: Option<Res<Archetypes>>,
+    mut app_state: ResMut<State<AppState>>,
+    mut change_map_writer: EventWriter<ChangeMap>,
+) {
+    let data_files = [
+        (
+            SHEETS_PATH,
+            asset_server.get_load_state(&sheet_defs_handle.0),
+        ),
+        (FISH_PATH, asset_server.get_load_state(&fish_defs_handle.0)),
+    ];
+    if let Some((file, _)) = data_files
+        .iter()
+        .find(|(_, load_state)| *load_state == LoadState::Failed)
+    {
+        let error = LevelError::new(file, LevelErrorKind::LoadFailed);
+        show_broken_level(&mut commands, &mut app_state, None, error);
+        return;
+    }
+
+    let levels = match levels {
+        Some(levels) => levels,
+        None => {
+            if let Some(manifest) = manifests.get(&manifest_handle.0) {
+                let levels = manifest
+                    .levels
+                    .iter()
+                    .map(|info| Level {
+                        info: info.clone(),
+                        map: asset_server.load(info.file.as_str()),
+                    })
+                    .collect();
+                commands.insert_resource(Levels(levels));
+            } else if asset_server.get_load_state(&manifest_handle.0) == LoadState::Failed {
+                let error = LevelError::new(MANIFEST_PATH, LevelErrorKind::LoadFailed);
+                show_broken_level(&mut commands, &mut app_state, None, error);
+            }
+            return;
+        }
+    };
+
+    let failed = levels
+        .0
+        .iter()
+        .position(|level| asset_server.get_load_state(&level.map) == LoadState::Failed);
+    if let Some(index) = failed {
+        let error = LevelError::new(&levels.0[index].info.file, LevelErrorKind::LoadFailed);
+        show_broken_level(&mut commands, &mut app_state, Some(index), error);
+        return;
+    }
+
+    if levels.0.is_empty() {
+        let error = LevelError::new(MANIFEST_PATH, LevelErrorKind::NoLevels);
+        show_broken_level(&mut commands, &mut app_state, None, error);
+    } else if archetypes.is_some()
+        && levels
+            .0
+            .iter()
+            .all(|level| tmx_maps.get(&level.map).is_some())
+    {
+        info!("Loaded {} levels", levels.0.len());
+        change_map_writer.send(ChangeMap { index: 0 });
+        app_state.set(AppState::InGame).unwrap();
+    }
+}
@@ -0,0 +1,6 @@
+//! Level data shared by the game and `levelcheck`.
+
+pub mod advantage;
+pub mod fish;
+pub mod properties;
+pub mod tmx;
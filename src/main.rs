@@ -1,47 +1,60 @@
 use advantage::{Advantage, EnemyAdvantage};
 use benimator::*;
+use bevy::input::InputSystem;
 use bevy::prelude::*;
+use bevy_game_jam::{advantage, fish, properties, tmx};
 use heron::*;
-use hud::{spawn_hud, update_advantage, update_hp_meter, fade_out_hint};
-use instant::Instant;
-use std::env;
-use std::time::Duration;
-
-mod advantage;
+use hud::{
+    fade_out_hint, spawn_hud, update_advantage, update_hp_meter, update_level_name,
+    update_oxygen_meter, update_score,
+};
+use ron_asset::RonLoader;
+
+mod ai;
+mod animation;
+mod bubble;
+mod combat;
 mod enemy;
 mod goal;
 mod hud;
+mod input;
+mod levels;
+mod menu;
 mod player;
+mod ron_asset;
+mod special;
 mod tilemap;
-mod bubble;
+mod tuning;
+mod water;
 
 #[derive(Component)]
-struct MainCamera;
-
-#[derive(Default)]
-pub struct Hit(bool);
-
-pub struct HitTime(Instant);
+pub struct MainCamera;
 
 pub struct Hp(pub u8);
 
 const PIXEL_MULTIPLIER: f32 = 3.0;
 
+/// Vertical acceleration of falling bodies, in pixels per second squared
+pub const GRAVITY: f32 = -1500.0;
+
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
-enum AppState {
+pub enum AppState {
+    Loading,
     InGame,
+    Paused,
+    BrokenLevel,
     Died,
     Won,
 }
 
 #[derive(Debug, Clone)]
-enum PlayerCollisionEventType {
+pub enum PlayerCollisionEventType {
     Started,
     Stopped,
 }
 
 #[derive(Debug, Clone)]
-struct PlayerCollision {
+pub struct PlayerCollision {
     pub player: CollisionData,
     pub other: CollisionData,
     pub event_type: PlayerCollisionEventType,
@@ -49,49 +62,110 @@ struct PlayerCollision {
 
 fn main() {
     App::new()
-        .add_state(AppState::InGame)
-        .init_resource::<enemy::Animations>()
+        .add_state(AppState::Loading)
         .add_plugins(DefaultPlugins)
         .add_plugin(PhysicsPlugin::default())
         .add_plugin(AnimationPlugin::default())
-        .add_system(bevy::input::system::exit_on_esc_system)
+        .add_asset::<levels::LevelManifest>()
+        .add_asset::<levels::TmxMap>()
+        .add_asset::<fish::FishDefs>()
+        .add_asset::<animation::SheetDefs>()
+        .add_asset::<tuning::Tuning>()
+        .add_asset_loader(RonLoader::<levels::LevelManifest>::new(&["levels.ron"]))
+        .init_asset_loader::<levels::TmxLoader>()
+        .add_asset_loader(
+            RonLoader::<fish::FishDefs>::new(&["fish.ron"])
+                .with_check(|defs| defs.check().map_err(Into::into)),
+        )
+        .add_asset_loader(RonLoader::<animation::SheetDefs>::new(&["sheets.ron"]))
+        .add_asset_loader(RonLoader::<tuning::Tuning>::new(&["tuning.ron"]))
+        .add_system(menu::exit_on_esc.label("exit"))
         .insert_resource(ClearColor(Color::hex("29366f").unwrap()))
-        .insert_resource(Gravity::from(Vec2::new(0.0, -1500.0)))
-        .insert_resource(player::Jump(0))
-        .insert_resource(Hit(false))
-        .insert_resource(HitTime(Instant::now()))
+        .insert_resource(Gravity::from(Vec2::new(0.0, GRAVITY)))
+        .init_resource::<player::ControllerSettings>()
         .insert_resource(Hp(5))
+        .init_resource::<combat::Score>()
+        .init_resource::<player::DamageSettings>()
+        .init_resource::<water::SwimSettings>()
+        .init_resource::<water::Oxygen>()
         .insert_resource(Advantage::random())
+        .insert_resource(input::Bindings::load())
+        .init_resource::<input::ActionState>()
+        .init_resource::<menu::Rebinding>()
+        .init_resource::<menu::MenuCursor>()
+        .add_system_to_stage(
+            CoreStage::PreUpdate,
+            input::update_actions.after(InputSystem),
+        )
+        .add_system(menu::toggle_pause.label("pause"))
         .add_startup_system(init)
         .add_startup_system(set_window_resolution)
-        .add_startup_system(tilemap::load_initial_map)
+        .add_startup_system(levels::start_loading)
+        .add_startup_system(enemy::start_loading)
+        .add_startup_system(animation::start_loading)
+        .add_startup_system(tuning::start_loading)
+        .add_system(animation::build_registry.label("registry"))
+        .add_system(enemy::build_archetypes.after("registry"))
+        .add_system(tuning::apply_tuning)
         .add_event::<PlayerCollision>()
         .add_event::<tilemap::ChangeMap>()
+        .add_event::<combat::EnemyHit>()
+        .add_event::<player::Damage>()
+        .add_system_set(
+            SystemSet::on_update(AppState::Loading).with_system(levels::wait_for_levels),
+        )
+        .add_system_set(SystemSet::on_enter(AppState::BrokenLevel).with_system(on_broken_level))
+        .add_system_set(
+            SystemSet::on_update(AppState::BrokenLevel).with_system(tilemap::retry_broken_level),
+        )
+        .add_system_set(
+            SystemSet::on_exit(AppState::BrokenLevel).with_system(on_broken_level_fixed),
+        )
         .add_system_set(
             SystemSet::on_update(AppState::InGame)
-                .with_system(player::r#move)
+                .with_system(player::detect_ground.label("ground"))
+                .with_system(water::detect_water.label("water"))
+                .with_system(water::breathe.label("drowning").after("water"))
+                .with_system(player::r#move.label("move").after("ground").after("water"))
+                .with_system(player::animate.after("move"))
                 .with_system(check_collisions.label("collisions"))
-                .with_system(enemy::r#move)
-                .with_system(cameraman)
-                .with_system(check_hits)
+                .with_system(ai::update_state.label("ai"))
+                .with_system(ai::plan_routes.label("routes").after("ai"))
+                .with_system(ai::steer.label("steer").after("routes"))
+                .with_system(special::track_contacts.after("collisions"))
+                .with_system(special::attack.after("steer"))
+                .with_system(ai::draw_routes.after("routes"))
+                .with_system(cameraman.label("camera"))
+                .with_system(tilemap::scroll_parallax_layers.after("camera"))
+                .with_system(check_hits.label("bites"))
+                .with_system(player::take_damage.after("bites").after("drowning"))
+                .with_system(player::flash_invulnerable)
+                .with_system(combat::stomp.label("hits").after("collisions"))
+                .with_system(combat::pinch.label("hits"))
+                .with_system(combat::apply_hits.after("hits"))
                 .with_system(bubble::process_bubble_generators)
                 .with_system(bubble::process_bubbles)
                 .with_system(handle_player_collisions.after("collisions"))
-                .with_system(tilemap::handle_change_map.after("collisions")),
+                .with_system(tilemap::handle_change_map.after("collisions"))
+                .with_system(tilemap::reload_changed_map),
         )
+        .add_system_set(SystemSet::on_enter(AppState::Paused).with_system(menu::spawn_menu))
         .add_system_set(
-            SystemSet::on_enter(AppState::Died)
-                .with_system(on_die)
+            SystemSet::on_update(AppState::Paused)
+                .with_system(menu::rebind.after("pause").after("exit"))
+                .with_system(menu::update_menu),
         )
-        .add_system_set(
-            SystemSet::on_enter(AppState::Won)
-                .with_system(on_win)
-        )
-
+        .add_system_set(SystemSet::on_exit(AppState::Paused).with_system(menu::despawn_menu))
+        .add_system_set(SystemSet::on_enter(AppState::Died).with_system(player::play_death))
+        .add_system_set(SystemSet::on_update(AppState::Died).with_system(on_die))
+        .add_system_set(SystemSet::on_enter(AppState::Won).with_system(on_win))
         // HUD
         .add_startup_system(spawn_hud)
         .add_system(update_hp_meter)
+        .add_system(update_oxygen_meter)
+        .add_system(update_score)
         .add_system(update_advantage)
+        .add_system(update_level_name)
         .add_system(fade_out_hint)
         .run()
 }
@@ -99,8 +173,8 @@ fn main() {
 fn init(mut commands: Commands) {
     let mut camera_bundle = OrthographicCameraBundle::new_2d();
     camera_bundle.orthographic_projection.scale = 1.0 / PIXEL_MULTIPLIER;
-    camera_bundle.transform.translation.x = tilemap::TILE_SIZE as f32 * 8.0;
-    camera_bundle.transform.translation.y = tilemap::TILE_SIZE as f32 * 11.0;
+    camera_bundle.transform.translation.x = tmx::TILE_SIZE as f32 * 8.0;
+    camera_bundle.transform.translation.y = tmx::TILE_SIZE as f32 * 11.0;
     commands.spawn_bundle(camera_bundle).insert(MainCamera);
 }
 
@@ -116,7 +190,10 @@ fn check_collisions(
     player_entity: Query<Entity, With<player::Player>>,
     mut player_collision_writer: EventWriter<PlayerCollision>,
 ) {
-    let id = player_entity.single();
+    let id = match player_entity.get_single() {
+        Ok(id) => id,
+        Err(_) => return,
+    };
     for event in events.iter() {
         match event {
             CollisionEvent::Started(player, other) if player.rigid_body_entity() == id => {
@@ -155,49 +232,39 @@ fn check_collisions(
 fn handle_player_collisions(
     mut commands: Commands,
     mut app_state: ResMut<State<AppState>>,
-    mut jump: ResMut<player::Jump>,
-    mut hit: ResMut<Hit>,
-    mut hit_time: ResMut<HitTime>,
     mut hp: ResMut<Hp>,
     adv: Res<Advantage>,
-    enemy: Query<Entity, With<enemy::Enemy>>,
-    stars: Query<Entity, With<Star>>,
+    mut enemy: Query<&mut enemy::Bite>,
+    special_hitboxes: Query<(), With<special::SpecialHitbox>>,
+    stars: Query<&Star>,
     goals: Query<&goal::Goal>,
     map: Query<&tilemap::Map>,
+    levels: Res<levels::Levels>,
     mut player_collision_reader: EventReader<PlayerCollision>,
     mut change_map_writer: EventWriter<tilemap::ChangeMap>,
 ) {
     for PlayerCollision {
-        player,
-        other,
-        event_type,
+        other, event_type, ..
     } in player_collision_reader.iter()
     {
-        if player.normals().iter().any(|normal| normal.y >= 0.9) {
-            jump.0 = 0;
-        }
-
         let other_entity = other.rigid_body_entity();
-        if enemy.get(other_entity).is_ok() {
+        // Special attacks hit the player on their own, they are not bites
+        let is_special = special_hitboxes.get(other.collision_shape_entity()).is_ok();
+        if let (Ok(mut bite), false) = (enemy.get_mut(other_entity), is_special) {
             match event_type {
-                PlayerCollisionEventType::Started => {
-                    hit.0 = true;
-                    hit_time.0 = Instant::now();
-                }
-                PlayerCollisionEventType::Stopped => {
-                    hit.0 = false;
-                }
+                PlayerCollisionEventType::Started => bite.start_contact(),
+                PlayerCollisionEventType::Stopped => bite.stop_contact(),
             }
         }
 
-        if stars.get(other_entity).is_ok() {
+        if let Ok(star) = stars.get(other_entity) {
             if matches!(
                 adv.as_ref(),
                 Advantage::Player(advantage::PlayerAdvantage::DoubleInitialHp)
             ) {
-                hp.0 += 2;
+                hp.0 = hp.0.saturating_add(star.value.saturating_mul(2));
             } else {
-                hp.0 += 1;
+                hp.0 = hp.0.saturating_add(star.value);
             };
 
             commands.entity(other_entity).despawn();
@@ -205,45 +272,48 @@ fn handle_player_collisions(
 
         if goals.get(other_entity).is_ok() {
             let map_component = map.single();
-            info!("Goal reached, changing map to {}", map_component.index + 1);
 
-            if env::var_os("CHANGE_MAP").is_some() {
-                // TODO: changing map does not work, hangs
-                change_map_writer.send(tilemap::ChangeMap {
-                    index: (map_component.index + 1) % tilemap::MAPS_COUNT,
-                })
+            let next_index = map_component.index + 1;
+            if next_index < levels.0.len() {
+                info!("Goal reached, changing map to {}", next_index);
+                change_map_writer.send(tilemap::ChangeMap { index: next_index });
             } else {
+                info!("Goal reached on the last map");
                 app_state.set(AppState::Won).unwrap();
             }
         }
     }
 }
 
+/// Every fish touching the player bites on its own cooldown, unless stunned
 fn check_hits(
-    hit: ResMut<Hit>,
-    mut hit_time: ResMut<HitTime>,
-    mut hp: ResMut<Hp>,
+    time: Res<Time>,
+    mut bites: Query<(&mut enemy::Bite, &ai::AiState, &Transform)>,
     advantage: Res<Advantage>,
-    mut app_state: ResMut<State<AppState>>
+    mut damages: EventWriter<player::Damage>,
 ) {
-    if hit.0 && hit_time.0.elapsed().as_millis() > 300 {
-        let bite_strength = if matches!(
-            advantage.as_ref(),
-            Advantage::Enemy(EnemyAdvantage::DoubleBite)
-        ) {
-            3
-        } else {
-            1
-        };
-
-        if hp.0 > bite_strength {
-            hp.0 -= bite_strength;
-        } else {
-            hp.0 = 0;
-            app_state.set(AppState::Died).unwrap();
+    let multiplier = if matches!(
+        advantage.as_ref(),
+        Advantage::Enemy(EnemyAdvantage::DoubleBite)
+    ) {
+        3
+    } else {
+        1
+    };
+
+    for (mut bite, state, transform) in bites.iter_mut() {
+        if !bite.is_biting() || matches!(state, ai::AiState::Stunned(_)) {
+            continue;
+        }
+        bite.cooldown.tick(time.delta());
+        if !bite.cooldown.just_finished() {
+            continue;
         }
 
-        hit_time.0 = Instant::now();
+        damages.send(player::Damage {
+            amount: bite.strength.saturating_mul(multiplier),
+            from: Some(transform.translation.truncate()),
+        });
     }
 }
 
@@ -275,46 +345,55 @@ fn cameraman(
     }
 }
 
+const CLIP_STAR_SPIN: &str = "spin";
+
 #[derive(Component)]
-pub struct Star;
+pub struct Star {
+    /// HP given when collected
+    pub value: u8,
+}
 
 fn spawn_stars(
     commands: &mut Commands,
-    asset_server: &Res<AssetServer>,
+    registry: &animation::AnimationRegistry,
     position: Vec2,
-    textures: &mut ResMut<Assets<TextureAtlas>>,
-    animations: &mut ResMut<Assets<SpriteSheetAnimation>>,
+    properties: &properties::StarProperties,
 ) {
-
-    let animation_handle = animations.add(SpriteSheetAnimation::from_range(
-        0..=2,
-        Duration::from_millis(100),
-    ));
-
     commands
         .spawn_bundle(SpriteSheetBundle {
-            texture_atlas: textures.add(TextureAtlas::from_grid(
-                asset_server.load("star.png"),
-                Vec2::new(15.0, 15.0),
-                3,
-                1,
-            )),
+            texture_atlas: registry.texture_atlas(animation::SHEET_STAR),
             transform: Transform {
                 translation: Vec3::new(position.x, position.y, 4.0),
                 ..Default::default()
             },
             ..Default::default()
         })
-        .insert(Star)
+        .insert(Star {
+            value: properties.value,
+        })
         .insert(RigidBody::Static)
-        .insert(animation_handle)
+        .insert(registry.clip(animation::SHEET_STAR, CLIP_STAR_SPIN))
         .insert(Play)
         .with_children(|children| {
             children.spawn_bundle((SensorShape, CollisionShape::Sphere { radius: 5.0 }));
         });
 }
 
-fn on_die(mut commands: Commands, asset_server: Res<AssetServer>) {
+/// Covers the level once the death clip of the player has played
+fn on_die(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    finished_clips: RemovedComponents<Play>,
+    player: Query<Entity, With<player::Player>>,
+) {
+    let player = match player.get_single() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+    if !finished_clips.iter().any(|entity| entity == player) {
+        return;
+    }
+
     commands.spawn_bundle(SpriteBundle {
         sprite: Sprite {
             color: Color::BLACK,
@@ -346,6 +425,58 @@ fn on_die(mut commands: Commands, asset_server: Res<AssetServer>) {
     });
 }
 
+#[derive(Component)]
+struct BrokenLevelScreen;
+
+fn on_broken_level(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    broken_level: Res<tilemap::BrokenLevel>,
+) {
+    commands
+        .spawn_bundle(SpriteBundle {
+            sprite: Sprite {
+                color: Color::BLACK,
+                custom_size: Some(Vec2::new(10000.0, 10000.0)),
+                ..Default::default()
+            },
+            transform: Transform::from_translation(Vec3::new(0.0, 0.0, 100.0)),
+            ..Default::default()
+        })
+        .insert(BrokenLevelScreen);
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                max_size: Size::new(Val::Percent(90.0), Val::Undefined),
+                position: Rect {
+                    left: Val::Percent(5.0),
+                    top: Val::Percent(40.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text::with_section(
+                format!("Broken level\n{}", broken_level.error),
+                TextStyle {
+                    font: asset_server.load("PublicPixel-0W6DP.ttf"),
+                    font_size: 18.0,
+                    color: Color::RED,
+                },
+                Default::default(),
+            ),
+            ..Default::default()
+        })
+        .insert(BrokenLevelScreen);
+}
+
+fn on_broken_level_fixed(mut commands: Commands, screen: Query<Entity, With<BrokenLevelScreen>>) {
+    for entity in screen.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    commands.remove_resource::<tilemap::BrokenLevel>();
+}
+
 fn on_win(mut commands: Commands, asset_server: Res<AssetServer>) {
 
     commands.spawn_bundle(SpriteBundle {
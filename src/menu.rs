@@ -0,0 +1,176 @@
+//! Pause menu, listing the bindings of every action and changing them
+
+use crate::input::{just_pressed_binding, Action, ActionState, Bindings, ACTIONS};
+use crate::AppState;
+use bevy::app::AppExit;
+use bevy::input::gamepad::{GamepadAxis, GamepadButton, Gamepads};
+use bevy::prelude::*;
+use heron::PhysicsTime;
+
+/// Pressed in the menu to pick an action from `ACTIONS` to rebind
+const SELECT_KEYS: [KeyCode; 6] = [
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+];
+
+#[derive(Component)]
+pub struct PauseMenu;
+
+#[derive(Component)]
+pub struct BindingsLabel;
+
+/// Action waiting for the player to press its new binding
+#[derive(Default)]
+pub struct Rebinding(pub Option<Action>);
+
+/// Index in `ACTIONS` of the action highlighted in the menu
+#[derive(Default)]
+pub struct MenuCursor(pub usize);
+
+/// Quits on Esc, which instead cancels rebinding while the menu waits for a binding
+pub fn exit_on_esc(
+    keys: Res<Input<KeyCode>>,
+    rebinding: Res<Rebinding>,
+    mut exit: EventWriter<AppExit>,
+) {
+    if keys.just_pressed(KeyCode::Escape) && rebinding.0.is_none() {
+        exit.send(AppExit);
+    }
+}
+
+pub fn toggle_pause(
+    actions: Res<ActionState>,
+    rebinding: Res<Rebinding>,
+    mut app_state: ResMut<State<AppState>>,
+    mut physics_time: ResMut<PhysicsTime>,
+) {
+    if !actions.just_pressed(Action::Pause) || rebinding.0.is_some() {
+        return;
+    }
+    match app_state.current() {
+        AppState::InGame => {
+            physics_time.pause();
+            app_state.push(AppState::Paused).unwrap();
+        }
+        AppState::Paused => {
+            physics_time.resume();
+            app_state.pop().unwrap();
+        }
+        _ => (),
+    }
+}
+
+pub fn spawn_menu(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            color: Color::rgba(0.0, 0.0, 0.0, 0.7).into(),
+            ..Default::default()
+        })
+        .insert(PauseMenu)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        "",
+                        TextStyle {
+                            font: asset_server.load("PublicPixel-0W6DP.ttf"),
+                            font_size: 18.0,
+                            color: Color::WHITE,
+                        },
+                        Default::default(),
+                    ),
+                    ..Default::default()
+                })
+                .insert(BindingsLabel);
+        });
+}
+
+pub fn despawn_menu(
+    mut commands: Commands,
+    mut rebinding: ResMut<Rebinding>,
+    menu: Query<Entity, With<PauseMenu>>,
+) {
+    for entity in menu.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    rebinding.0 = None;
+}
+
+/// Menu actions or number keys pick an action, the next key, button or stick pushed
+/// becomes its binding
+#[allow(clippy::too_many_arguments)]
+pub fn rebind(
+    actions: Res<ActionState>,
+    keys: Res<Input<KeyCode>>,
+    buttons: Res<Input<GamepadButton>>,
+    axes: Res<Axis<GamepadAxis>>,
+    gamepads: Res<Gamepads>,
+    mut bindings: ResMut<Bindings>,
+    mut rebinding: ResMut<Rebinding>,
+    mut cursor: ResMut<MenuCursor>,
+) {
+    match rebinding.0 {
+        Some(_) if keys.just_pressed(KeyCode::Escape) => rebinding.0 = None,
+        Some(action) => {
+            if let Some(binding) = just_pressed_binding(&keys, &buttons, &axes, &gamepads) {
+                info!("Binding {} to {:?}", binding, action);
+                bindings.rebind(action, binding);
+                bindings.save();
+                rebinding.0 = None;
+            }
+        }
+        None => {
+            if actions.just_pressed(Action::MenuUp) {
+                cursor.0 = (cursor.0 + ACTIONS.len() - 1) % ACTIONS.len();
+            }
+            if actions.just_pressed(Action::MenuDown) {
+                cursor.0 = (cursor.0 + 1) % ACTIONS.len();
+            }
+            let selected = SELECT_KEYS
+                .iter()
+                .position(|&key| keys.just_pressed(key))
+                .or_else(|| actions.just_pressed(Action::MenuSelect).then(|| cursor.0));
+            if let Some(index) = selected {
+                cursor.0 = index;
+                rebinding.0 = Some(ACTIONS[index]);
+            }
+        }
+    }
+}
+
+pub fn update_menu(
+    bindings: Res<Bindings>,
+    rebinding: Res<Rebinding>,
+    cursor: Res<MenuCursor>,
+    mut label: Query<&mut Text, With<BindingsLabel>>,
+) {
+    let mut text = String::from("Paused\n\nPick an action to change its controls\n\n");
+    for (index, action) in ACTIONS.into_iter().enumerate() {
+        let marker = if index == cursor.0 { ">" } else { " " };
+        let bound = if rebinding.0 == Some(action) {
+            "press a key or button, Esc cancels...".to_string()
+        } else {
+            let names: Vec<String> = bindings
+                .of(action)
+                .iter()
+                .map(ToString::to_string)
+                .collect();
+            names.join(", ")
+        };
+        text += &format!("{} {} {:?}: {}\n", marker, index + 1, action, bound);
+    }
+
+    if let Ok(mut label) = label.get_single_mut() {
+        label.sections[0].value = text;
+    }
+}
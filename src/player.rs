@@ -1,34 +1,150 @@
 use crate::advantage::{Advantage, PlayerAdvantage};
+use crate::animation::{AnimationRegistry, SHEET_PLAYER};
+use crate::enemy::Direction;
+use crate::input::{Action, ActionState};
+use crate::water::{SwimSettings, Swimming};
+use crate::{AppState, Hp};
 use benimator::*;
+use bevy::math::const_vec2;
 use bevy::prelude::*;
+use heron::rapier_plugin::PhysicsWorld;
 use heron::*;
-use std::time::Duration;
+use serde::Deserialize;
 
 #[derive(Component)]
 pub struct Player;
 
-#[derive(Default)]
-pub struct Jump(pub u8);
+pub const SIZE: Vec2 = const_vec2!([32.0, 16.0]);
 
-pub fn spawn(
-    commands: &mut Commands,
-    asset_server: &Res<AssetServer>,
-    texture_atlases: &mut ResMut<Assets<TextureAtlas>>,
-    animations: &mut ResMut<Assets<SpriteSheetAnimation>>,
-    position: Vec2,
-) {
-    let texture = asset_server.load("ferris-Sheet.png");
-    let texture_atlas = TextureAtlas::from_grid(texture, Vec2::new(32.0, 32.0), 4, 1);
-    let texture_atlas_handle = texture_atlases.add(texture_atlas);
+/// Slower than this the player stands still, and keeps facing the same way
+const MIN_WALK_SPEED: f32 = 10.0;
+/// Seconds the hurt clip plays after a bite, within the invulnerability that follows
+const HURT_SECONDS: f32 = 0.3;
+/// Distance below the player that still counts as standing on the ground
+const GROUND_PROBE: f32 = 2.0;
+/// Distance from the sides of the player to the outer ground rays, so walls beside
+/// the player are not mistaken for ground
+const GROUND_RAY_INSET: f32 = 2.0;
+
+/// How the player moves and jumps, tuned under `controller` in `player.tuning.ron`
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ControllerSettings {
+    /// Running speed, in pixels per second
+    pub max_speed: f32,
+    /// Speed gained per second while running
+    pub acceleration: f32,
+    /// Speed lost per second when stopping or turning around
+    pub deceleration: f32,
+    /// Fraction of acceleration and deceleration the player has in the air
+    pub air_control: f32,
+    pub jump_speed: f32,
+    /// Fraction of upward speed kept when jump is released early
+    pub jump_cut: f32,
+    /// Seconds after walking off a ledge the player can still jump
+    pub coyote_time: f32,
+    /// Seconds a jump pressed before landing is remembered
+    pub jump_buffer: f32,
+    pub dive_speed: f32,
+}
+
+impl Default for ControllerSettings {
+    fn default() -> Self {
+        ControllerSettings {
+            max_speed: 200.0,
+            acceleration: 1600.0,
+            deceleration: 2400.0,
+            air_control: 0.6,
+            jump_speed: 600.0,
+            jump_cut: 0.5,
+            coyote_time: 0.1,
+            jump_buffer: 0.12,
+            dive_speed: 400.0,
+        }
+    }
+}
+
+/// Whether the player stands on something solid, updated every frame by `detect_ground`
+#[derive(Component, Default)]
+pub struct Grounded(pub bool);
+
+/// Movement state of the player between frames
+#[derive(Component, Default)]
+pub struct Controller {
+    /// Seconds left to jump as if still on the ground
+    coyote: f32,
+    /// Seconds left for a jump that was pressed too early
+    buffered_jump: f32,
+    /// Jumps left before landing again, with the double jump advantage
+    air_jumps: u8,
+    /// Rising from a jump that can still be cut short
+    rising: bool,
+}
 
-    let animation_handle = animations.add(SpriteSheetAnimation::from_range(
-        0..=3,
-        Duration::from_millis(100),
-    ));
+/// How the player reacts to bites, tuned under `damage`
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct DamageSettings {
+    /// Seconds the player cannot be bitten again after a bite
+    pub invulnerability: f32,
+    /// Seconds the sprite stays hidden or shown while flashing
+    pub flash_interval: f32,
+    /// Speed the player is pushed with, away from the fish and up
+    pub knockback: Vec2,
+}
 
+impl Default for DamageSettings {
+    fn default() -> Self {
+        DamageSettings {
+            invulnerability: 1.0,
+            flash_interval: 0.1,
+            knockback: Vec2::new(250.0, 300.0),
+        }
+    }
+}
+
+/// Bite that landed on the player, or HP lost to drowning
+pub struct Damage {
+    pub amount: u8,
+    /// Position of the fish that bit, `None` for damage without knockback and invulnerability
+    pub from: Option<Vec2>,
+}
+
+/// Player cannot be bitten until the timer finishes, and flashes meanwhile
+#[derive(Component)]
+pub struct Invulnerable(pub Timer);
+
+/// What the player is doing, picks the clip of the player sprite sheet that plays
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlayerAnimation {
+    Idle,
+    Walk,
+    Jump,
+    Fall,
+    Swim,
+    Hurt,
+    Dead,
+}
+
+impl PlayerAnimation {
+    /// Name of the clip in `assets/sprites.sheets.ron`
+    fn clip(self) -> &'static str {
+        match self {
+            PlayerAnimation::Idle => "idle",
+            PlayerAnimation::Walk => "walk",
+            PlayerAnimation::Jump => "jump",
+            PlayerAnimation::Fall => "fall",
+            PlayerAnimation::Swim => "swim",
+            PlayerAnimation::Hurt => "hurt",
+            PlayerAnimation::Dead => "dead",
+        }
+    }
+}
+
+pub fn spawn(commands: &mut Commands, registry: &AnimationRegistry, position: Vec2) {
     commands
         .spawn_bundle(SpriteSheetBundle {
-            texture_atlas: texture_atlas_handle.clone(),
+            texture_atlas: registry.texture_atlas(SHEET_PLAYER),
             sprite: TextureAtlasSprite {
                 index: 0,
                 ..Default::default()
@@ -38,7 +154,7 @@ pub fn spawn(
         })
         .insert(RigidBody::Dynamic)
         .insert(CollisionShape::Cuboid {
-            half_extends: Vec3::new(32.0 / 2.0, 16.0 / 2.0, 0.0),
+            half_extends: (SIZE / 2.0).extend(0.0),
             border_radius: None,
         })
         .insert(Velocity::from(Vec3::new(0.0, 0.0, 0.0)))
@@ -48,49 +164,335 @@ pub fn spawn(
             ..Default::default()
         })
         .insert(Player)
-        .insert(animation_handle)
+        .insert(Controller::default())
+        .insert(Grounded::default())
+        .insert(Swimming::default())
+        .insert(Acceleration::from_linear(Vec3::ZERO))
+        .insert(Direction::Right)
+        .insert(PlayerAnimation::Idle)
+        .insert(registry.clip(SHEET_PLAYER, PlayerAnimation::Idle.clip()))
         .insert(Play);
 }
 
+/// Casts rays down from the bottom of the player, any solid body they hit is ground.
+/// Sensors, like bite ranges and stars, are not.
+pub fn detect_ground(
+    physics_world: PhysicsWorld,
+    mut player: Query<(Entity, &Transform, &mut Grounded), With<Player>>,
+    sensor_shapes: Query<(), With<SensorShape>>,
+    bodies: Query<&RigidBody>,
+) {
+    let (id, transform, mut grounded) = match player.get_single_mut() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+    let is_solid = |entity: Entity| {
+        entity != id
+            && sensor_shapes.get(entity).is_err()
+            && !matches!(bodies.get(entity), Ok(RigidBody::Sensor))
+    };
+    let ray = Vec3::new(0.0, -(SIZE.y / 2.0 + GROUND_PROBE), 0.0);
+    let edge = SIZE.x / 2.0 - GROUND_RAY_INSET;
+
+    // Rays at both edges and the middle, so the player stands on ledges and across seams
+    let on_ground = [-edge, 0.0, edge].into_iter().any(|offset| {
+        let start = transform.translation + Vec3::new(offset, 0.0, 0.0);
+        physics_world
+            .ray_cast_with_filter(start, ray, true, CollisionLayers::default(), is_solid)
+            .is_some()
+    });
+    // Only write on changes, so change detection tells when the player lands or leaves
+    if grounded.0 != on_ground {
+        grounded.0 = on_ground;
+    }
+}
+
+#[allow(clippy::type_complexity)]
 pub fn r#move(
-    mut commands: Commands,
-    mut player: Query<(Entity, &mut Velocity), With<Player>>,
+    time: Res<Time>,
+    settings: Res<ControllerSettings>,
+    swim: Res<SwimSettings>,
+    mut player: Query<(&mut Velocity, &mut Controller, &Grounded, &Swimming), With<Player>>,
+    actions: Res<ActionState>,
     keys: Res<Input<KeyCode>>,
-    mut jump: ResMut<Jump>,
     mut adv: ResMut<Advantage>,
 ) {
-    let (id, mut player) = player.single_mut();
-    let max_jumps = if matches!(adv.as_ref(), Advantage::Player(PlayerAdvantage::DoubleJump)) {
-        2
+    let (mut velocity, mut controller, grounded, swimming) = match player.get_single_mut() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+    let dt = time.delta_seconds();
+    // The ground stays in reach for a few frames after jumping
+    let grounded = grounded.0 && !controller.rising;
+
+    if swimming.0 {
+        // Strokes replace jumps, leaving the water starts without a jump in hand
+        controller.coyote = 0.0;
+        controller.buffered_jump = 0.0;
+        controller.rising = false;
+        if actions.just_pressed(Action::Jump) {
+            velocity.linear.y = velocity.linear.y.max(swim.stroke_speed);
+        }
+        if actions.pressed(Action::Dive) {
+            velocity.linear.y = velocity.linear.y.min(-swim.dive_speed);
+        }
+        velocity.linear.y /= 1.0 + swim.drag * dt;
     } else {
-        1
+        jump(
+            &settings,
+            &actions,
+            &adv,
+            &mut velocity,
+            &mut controller,
+            grounded,
+            dt,
+        );
+        if actions.pressed(Action::Dive) {
+            velocity.linear.y = -settings.dive_speed;
+        }
+    }
+
+    let input = match (
+        actions.pressed(Action::MoveLeft),
+        actions.pressed(Action::MoveRight),
+    ) {
+        (true, false) => -1.0,
+        (false, true) => 1.0,
+        _ => 0.0,
+    };
+    // Turning around slows down first, at the stopping rate
+    let mut rate = if input == 0.0 || input * velocity.linear.x < 0.0 {
+        settings.deceleration
+    } else {
+        settings.acceleration
+    };
+    let max_speed = if swimming.0 {
+        rate *= swim.control;
+        swim.max_speed
+    } else {
+        if !grounded {
+            rate *= settings.air_control;
+        }
+        settings.max_speed
     };
+    let target = input * max_speed;
+    let max_change = rate * dt;
+    velocity.linear.x += (target - velocity.linear.x).clamp(-max_change, max_change);
 
-    commands.entity(id).remove::<Play>();
-    let can_jump = jump.0 < max_jumps;
-    let is_not_jumping = jump.0 == 0;
+    if option_env!("CHEATS").is_some() && keys.just_pressed(KeyCode::R) {
+        *adv = Advantage::random();
+    }
+}
 
-    if keys.just_pressed(KeyCode::W) && can_jump {
-        player.linear[1] = 600.0;
-        jump.0 += 1;
+/// Coyote time, jump buffer and jump cut on land
+fn jump(
+    settings: &ControllerSettings,
+    actions: &ActionState,
+    adv: &Advantage,
+    velocity: &mut Velocity,
+    controller: &mut Controller,
+    grounded: bool,
+    dt: f32,
+) {
+    if grounded {
+        controller.coyote = settings.coyote_time;
+        controller.air_jumps = if matches!(adv, Advantage::Player(PlayerAdvantage::DoubleJump)) {
+            1
+        } else {
+            0
+        };
+    } else {
+        controller.coyote -= dt;
     }
-    if keys.pressed(KeyCode::A) {
-        player.linear[0] = -200.0;
-        if is_not_jumping {
-            commands.entity(id).insert(Play);
+    if actions.just_pressed(Action::Jump) {
+        controller.buffered_jump = settings.jump_buffer;
+    } else {
+        controller.buffered_jump -= dt;
+    }
+
+    if controller.buffered_jump > 0.0 && (controller.coyote > 0.0 || controller.air_jumps > 0) {
+        if controller.coyote <= 0.0 {
+            controller.air_jumps -= 1;
         }
+        velocity.linear.y = settings.jump_speed;
+        controller.buffered_jump = 0.0;
+        controller.coyote = 0.0;
+        controller.rising = true;
     }
-    if keys.pressed(KeyCode::D) {
-        player.linear[0] = 200.0;
-        if is_not_jumping {
-            commands.entity(id).insert(Play);
+    if controller.rising && velocity.linear.y <= 0.0 {
+        controller.rising = false;
+    } else if controller.rising && !actions.pressed(Action::Jump) {
+        velocity.linear.y *= settings.jump_cut;
+        controller.rising = false;
+    }
+}
+
+/// Takes HP for bites and drowning. Bites also push the player away from the fish and start
+/// invulnerability, which drowning goes through.
+pub fn take_damage(
+    mut commands: Commands,
+    settings: Res<DamageSettings>,
+    mut hp: ResMut<Hp>,
+    mut app_state: ResMut<State<AppState>>,
+    mut damages: EventReader<Damage>,
+    mut player: Query<(Entity, &Transform, &mut Velocity, Option<&Invulnerable>), With<Player>>,
+) {
+    // Only one bite of a frame lands, the strongest one
+    let mut amount = 0u8;
+    let mut bite: Option<(u8, Vec2)> = None;
+    for damage in damages.iter() {
+        match damage.from {
+            Some(from) if bite.map_or(true, |(strongest, _)| damage.amount > strongest) => {
+                bite = Some((damage.amount, from));
+            }
+            Some(_) => {}
+            None => amount = amount.saturating_add(damage.amount),
         }
     }
-    if keys.pressed(KeyCode::S) {
-        player.linear[1] = -400.0;
+    if amount == 0 && bite.is_none() {
+        return;
+    }
+    let (entity, transform, mut velocity, invulnerable) = match player.get_single_mut() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+    let bite = bite.filter(|_| invulnerable.is_none());
+    if let Some((strength, _)) = bite {
+        amount = amount.saturating_add(strength);
+    }
+    if amount == 0 {
+        return;
     }
 
-    if option_env!("CHEATS").is_some() && keys.just_pressed(KeyCode::R) {
-        *adv = Advantage::random();
+    if hp.0 > amount {
+        hp.0 -= amount;
+    } else {
+        hp.0 = 0;
+        app_state.set(AppState::Died).unwrap();
+        return;
+    }
+
+    if let Some((_, from)) = bite {
+        let away = (transform.translation.x - from.x).signum();
+        velocity.linear = Vec3::new(away * settings.knockback.x, settings.knockback.y, 0.0);
+        commands
+            .entity(entity)
+            .insert(Invulnerable(Timer::from_seconds(
+                settings.invulnerability,
+                false,
+            )));
+    }
+}
+
+pub fn flash_invulnerable(
+    mut commands: Commands,
+    time: Res<Time>,
+    settings: Res<DamageSettings>,
+    mut player: Query<(Entity, &mut Invulnerable, &mut Visibility)>,
+) {
+    for (entity, mut invulnerable, mut visibility) in player.iter_mut() {
+        invulnerable.0.tick(time.delta());
+        if invulnerable.0.finished() {
+            visibility.is_visible = true;
+            commands.entity(entity).remove::<Invulnerable>();
+        } else {
+            let flashes = (invulnerable.0.elapsed_secs() / settings.flash_interval) as u32;
+            visibility.is_visible = flashes % 2 == 1;
+        }
+    }
+}
+
+/// Plays the clip of what the player is doing, and turns the sprite the way they move.
+/// The sheet is drawn facing right.
+#[allow(clippy::type_complexity)]
+pub fn animate(
+    mut commands: Commands,
+    registry: Res<AnimationRegistry>,
+    mut player: Query<
+        (
+            Entity,
+            &Velocity,
+            &Grounded,
+            &Swimming,
+            Option<&Invulnerable>,
+            &mut Direction,
+            &mut PlayerAnimation,
+            &mut Handle<SpriteSheetAnimation>,
+            &mut TextureAtlasSprite,
+        ),
+        With<Player>,
+    >,
+) {
+    let (
+        entity,
+        velocity,
+        grounded,
+        swimming,
+        invulnerable,
+        mut direction,
+        mut state,
+        mut clip,
+        mut sprite,
+    ) = match player.get_single_mut() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+
+    let hurt = invulnerable.map_or(false, |invulnerable| {
+        invulnerable.0.elapsed_secs() < HURT_SECONDS
+    });
+    let walking = velocity.linear.x.abs() > MIN_WALK_SPEED;
+    let next = if hurt {
+        PlayerAnimation::Hurt
+    } else if !grounded.0 && swimming.0 {
+        PlayerAnimation::Swim
+    } else if !grounded.0 && velocity.linear.y > 0.0 {
+        PlayerAnimation::Jump
+    } else if !grounded.0 {
+        PlayerAnimation::Fall
+    } else if walking {
+        PlayerAnimation::Walk
+    } else {
+        PlayerAnimation::Idle
+    };
+
+    // Knocked back players keep facing the fish that bit them
+    if walking && !hurt {
+        *direction = Direction::towards(velocity.linear.x);
+    }
+    sprite.flip_x = matches!(*direction, Direction::Left);
+
+    if *state != next {
+        *state = next;
+        *clip = registry.clip(SHEET_PLAYER, next.clip());
+        // Clips played once stop by removing `Play`
+        commands.entity(entity).insert(Play);
+    }
+}
+
+/// Plays the `dead` clip once, the death screen waits for it to finish
+#[allow(clippy::type_complexity)]
+pub fn play_death(
+    mut commands: Commands,
+    registry: Res<AnimationRegistry>,
+    mut player: Query<
+        (
+            Entity,
+            &mut PlayerAnimation,
+            &mut Handle<SpriteSheetAnimation>,
+            &mut Visibility,
+        ),
+        With<Player>,
+    >,
+) {
+    if let Ok((entity, mut state, mut clip, mut visibility)) = player.get_single_mut() {
+        *state = PlayerAnimation::Dead;
+        *clip = registry.clip(SHEET_PLAYER, PlayerAnimation::Dead.clip());
+        // Flashing stops outside of the game, a player bitten to death could stay hidden
+        visibility.is_visible = true;
+        commands
+            .entity(entity)
+            .insert(Play)
+            .remove::<Invulnerable>();
     }
 }
@@ -0,0 +1,469 @@
+//! Gameplay settings read from custom properties of Tiled objects and layers.
+//! Every property is optional, anything not set in Tiled keeps its default.
+
+use crate::advantage::Advantage;
+use crate::tmx::ObjectKind;
+use bevy::math::Vec2;
+use std::fmt;
+use tiled::{Properties, PropertyValue};
+
+/// Distance an enemy patrols to each side of where it was placed, in pixels
+pub const PROPERTY_PATROL_RANGE: &str = "patrol_range";
+/// Horizontal enemy speed, in pixels per second
+pub const PROPERTY_SPEED: &str = "speed";
+/// HP taken by one bite
+pub const PROPERTY_BITE_STRENGTH: &str = "bite_strength";
+/// HP given by a star
+pub const PROPERTY_VALUE: &str = "value";
+/// Seconds between bubbles, random when not set
+pub const PROPERTY_INTERVAL: &str = "interval";
+
+/// Object only appears when the advantage favours its side: extra enemies, bonus stars
+pub const PROPERTY_ADVANTAGE: &str = "advantage";
+/// Object only appears with the advantage of this name, like `DoubleSpeed`
+pub const PROPERTY_ONLY_WITH_ADVANTAGE: &str = "only_with_advantage";
+
+/// Keeps a sprite entity per tile, for layers that change at runtime
+pub const PROPERTY_INDIVIDUAL_TILES: &str = "individual_tiles";
+/// Layer scroll factor relative to the camera, both axes or one of them
+pub const PROPERTY_PARALLAX: &str = "parallax";
+pub const PROPERTY_PARALLAX_X: &str = "parallax_x";
+pub const PROPERTY_PARALLAX_Y: &str = "parallax_y";
+
+/// Value type a Tiled property can be read as
+pub trait FromProperty: Sized {
+    /// Shown to level designers when the property has another type
+    const TYPE_NAME: &'static str;
+
+    fn from_property(value: &PropertyValue) -> Option<Self>;
+}
+
+impl FromProperty for bool {
+    const TYPE_NAME: &'static str = "bool";
+
+    fn from_property(value: &PropertyValue) -> Option<Self> {
+        match value {
+            PropertyValue::BoolValue(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+impl FromProperty for f32 {
+    const TYPE_NAME: &'static str = "float";
+
+    fn from_property(value: &PropertyValue) -> Option<Self> {
+        // Tiled saves whole numbers typed into an int property without a decimal point
+        match value {
+            PropertyValue::FloatValue(value) => Some(*value),
+            PropertyValue::IntValue(value) => Some(*value as f32),
+            _ => None,
+        }
+    }
+}
+
+impl FromProperty for u8 {
+    const TYPE_NAME: &'static str = "int from 0 to 255";
+
+    fn from_property(value: &PropertyValue) -> Option<Self> {
+        match value {
+            PropertyValue::IntValue(value) => u8::try_from(*value).ok(),
+            _ => None,
+        }
+    }
+}
+
+impl FromProperty for String {
+    const TYPE_NAME: &'static str = "string";
+
+    fn from_property(value: &PropertyValue) -> Option<Self> {
+        match value {
+            PropertyValue::StringValue(value) => Some(value.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl FromProperty for Advantage {
+    const TYPE_NAME: &'static str =
+        "DoubleJump, DoubleInitialHp, StrongClaws, DoubleBite or DoubleSpeed";
+
+    fn from_property(value: &PropertyValue) -> Option<Self> {
+        match value {
+            PropertyValue::StringValue(name) => Advantage::from_name(name),
+            _ => None,
+        }
+    }
+}
+
+/// Property is set, but the game cannot use its value
+#[derive(Debug, Clone, PartialEq)]
+pub enum PropertyError {
+    /// Value cannot be read as the type the game expects
+    WrongType {
+        name: String,
+        expected: &'static str,
+    },
+    /// Value has the right type, but is outside the values that work
+    OutOfRange {
+        name: String,
+        expected: &'static str,
+    },
+}
+
+impl fmt::Display for PropertyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PropertyError::WrongType { name, expected }
+            | PropertyError::OutOfRange { name, expected } => {
+                write!(f, "property \"{}\" must be {}", name, expected)
+            }
+        }
+    }
+}
+
+/// Reads property `name`, `Ok(None)` if it is not set
+pub fn get<T: FromProperty>(
+    properties: &Properties,
+    name: &str,
+) -> Result<Option<T>, PropertyError> {
+    match properties.get(name) {
+        Some(value) => T::from_property(value)
+            .map(Some)
+            .ok_or_else(|| PropertyError::WrongType {
+                name: name.to_string(),
+                expected: T::TYPE_NAME,
+            }),
+        None => Ok(None),
+    }
+}
+
+pub fn get_or<T: FromProperty>(
+    properties: &Properties,
+    name: &str,
+    default: T,
+) -> Result<T, PropertyError> {
+    Ok(get(properties, name)?.unwrap_or(default))
+}
+
+/// Value of property `name`, unless it is negative
+fn non_negative(name: &str, value: f32) -> Result<f32, PropertyError> {
+    if value >= 0.0 {
+        Ok(value)
+    } else {
+        Err(PropertyError::OutOfRange {
+            name: name.to_string(),
+            expected: "0 or more",
+        })
+    }
+}
+
+/// Value of property `name`, unless it is 0 or negative
+fn positive(name: &str, value: f32) -> Result<f32, PropertyError> {
+    if value > 0.0 {
+        Ok(value)
+    } else {
+        Err(PropertyError::OutOfRange {
+            name: name.to_string(),
+            expected: "more than 0",
+        })
+    }
+}
+
+/// Settings of enemy objects, anything not set comes from the fish archetype
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EnemyProperties {
+    pub patrol_range: f32,
+    pub speed: f32,
+    pub bite_strength: u8,
+}
+
+impl EnemyProperties {
+    pub fn from_properties(
+        properties: &Properties,
+        default: &EnemyProperties,
+    ) -> Result<Self, PropertyError> {
+        let patrol_range = get_or(properties, PROPERTY_PATROL_RANGE, default.patrol_range)?;
+        let speed = get_or(properties, PROPERTY_SPEED, default.speed)?;
+        Ok(EnemyProperties {
+            patrol_range: non_negative(PROPERTY_PATROL_RANGE, patrol_range)?,
+            speed: non_negative(PROPERTY_SPEED, speed)?,
+            bite_strength: get_or(properties, PROPERTY_BITE_STRENGTH, default.bite_strength)?,
+        })
+    }
+}
+
+/// Settings of `star` objects
+#[derive(Clone, Debug, PartialEq)]
+pub struct StarProperties {
+    pub value: u8,
+}
+
+impl Default for StarProperties {
+    fn default() -> Self {
+        StarProperties { value: 1 }
+    }
+}
+
+impl StarProperties {
+    pub fn from_properties(properties: &Properties) -> Result<Self, PropertyError> {
+        Ok(StarProperties {
+            value: get_or(properties, PROPERTY_VALUE, Self::default().value)?,
+        })
+    }
+}
+
+/// Settings of `bubble_generator` objects
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BubbleGeneratorProperties {
+    pub interval: Option<f32>,
+}
+
+impl BubbleGeneratorProperties {
+    pub fn from_properties(properties: &Properties) -> Result<Self, PropertyError> {
+        // Bubbles come out on a repeating timer, which cannot run with no interval
+        let interval = match get(properties, PROPERTY_INTERVAL)? {
+            Some(interval) => Some(positive(PROPERTY_INTERVAL, interval)?),
+            None => None,
+        };
+        Ok(BubbleGeneratorProperties { interval })
+    }
+}
+
+/// Decides if an object is spawned, given the advantage rolled for the level
+#[derive(Clone, Debug, PartialEq)]
+pub enum SpawnCondition {
+    Always,
+    /// When the advantage favours the side of the object, enemies or player
+    SideHasAdvantage,
+    OnlyWith(Advantage),
+}
+
+impl Default for SpawnCondition {
+    fn default() -> Self {
+        SpawnCondition::Always
+    }
+}
+
+impl SpawnCondition {
+    pub fn from_properties(properties: &Properties) -> Result<Self, PropertyError> {
+        let side_has_advantage = get_or(properties, PROPERTY_ADVANTAGE, false)?;
+        Ok(match get(properties, PROPERTY_ONLY_WITH_ADVANTAGE)? {
+            Some(advantage) => SpawnCondition::OnlyWith(advantage),
+            None if side_has_advantage => SpawnCondition::SideHasAdvantage,
+            None => SpawnCondition::Always,
+        })
+    }
+
+    /// `enemy` tells which side the object is on, stars and bubbles help the player
+    pub fn is_met(&self, enemy: bool, advantage: &Advantage) -> bool {
+        match self {
+            SpawnCondition::Always => true,
+            SpawnCondition::SideHasAdvantage => advantage.favours_enemies() == enemy,
+            SpawnCondition::OnlyWith(required) => required == advantage,
+        }
+    }
+}
+
+/// Settings of tile layers
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LayerProperties {
+    pub individual_tiles: bool,
+    /// Scroll factor, `None` for layers that move together with the world
+    pub parallax: Option<Vec2>,
+}
+
+impl LayerProperties {
+    pub fn from_properties(properties: &Properties) -> Result<Self, PropertyError> {
+        let both: Option<f32> = get(properties, PROPERTY_PARALLAX)?;
+        let x = get(properties, PROPERTY_PARALLAX_X)?.or(both);
+        let y = get(properties, PROPERTY_PARALLAX_Y)?.or(both);
+        let parallax = if x.is_none() && y.is_none() {
+            None
+        } else {
+            Some(Vec2::new(x.unwrap_or(1.0), y.unwrap_or(1.0)))
+        };
+
+        Ok(LayerProperties {
+            individual_tiles: get_or(properties, PROPERTY_INDIVIDUAL_TILES, false)?,
+            parallax,
+        })
+    }
+}
+
+/// Checks that the properties the game reads from an object have the right types
+pub fn check_object(object: &tiled::Object, kind: ObjectKind) -> Result<(), PropertyError> {
+    let properties = &object.properties;
+    match kind {
+        ObjectKind::Enemy => {
+            EnemyProperties::from_properties(properties, &EnemyProperties::default()).map(drop)
+        }
+        ObjectKind::Star => StarProperties::from_properties(properties).map(drop),
+        ObjectKind::BubbleGenerator => {
+            BubbleGeneratorProperties::from_properties(properties).map(drop)
+        }
+        ObjectKind::PlayerStart | ObjectKind::Goal | ObjectKind::Water | ObjectKind::AirPocket => {
+            Ok(())
+        }
+    }?;
+    if is_conditional(kind) {
+        SpawnCondition::from_properties(properties)?;
+    }
+    Ok(())
+}
+
+/// Objects that can depend on the advantage, level start and goal always exist
+pub fn is_conditional(kind: ObjectKind) -> bool {
+    matches!(
+        kind,
+        ObjectKind::Enemy | ObjectKind::Star | ObjectKind::BubbleGenerator
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn properties(values: &[(&str, PropertyValue)]) -> Properties {
+        values
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.clone()))
+            .collect()
+    }
+
+    fn enemy_defaults() -> EnemyProperties {
+        EnemyProperties {
+            patrol_range: 70.0,
+            speed: 100.0,
+            bite_strength: 1,
+        }
+    }
+
+    #[test]
+    fn missing_values_keep_defaults() {
+        let empty = properties(&[]);
+
+        assert_eq!(
+            EnemyProperties::from_properties(&empty, &enemy_defaults()),
+            Ok(enemy_defaults())
+        );
+        assert_eq!(
+            StarProperties::from_properties(&empty),
+            Ok(StarProperties { value: 1 })
+        );
+        assert_eq!(
+            BubbleGeneratorProperties::from_properties(&empty),
+            Ok(BubbleGeneratorProperties { interval: None })
+        );
+        assert_eq!(
+            SpawnCondition::from_properties(&empty),
+            Ok(SpawnCondition::Always)
+        );
+        assert_eq!(
+            LayerProperties::from_properties(&empty),
+            Ok(LayerProperties::default())
+        );
+    }
+
+    #[test]
+    fn set_values_replace_defaults() {
+        let set = properties(&[
+            (PROPERTY_PATROL_RANGE, PropertyValue::FloatValue(20.0)),
+            (PROPERTY_SPEED, PropertyValue::IntValue(50)),
+            (PROPERTY_BITE_STRENGTH, PropertyValue::IntValue(3)),
+        ]);
+
+        assert_eq!(
+            EnemyProperties::from_properties(&set, &enemy_defaults()),
+            Ok(EnemyProperties {
+                patrol_range: 20.0,
+                speed: 50.0,
+                bite_strength: 3,
+            })
+        );
+    }
+
+    #[test]
+    fn wrong_type_is_an_error() {
+        let speed = properties(&[(
+            PROPERTY_SPEED,
+            PropertyValue::StringValue("fast".to_string()),
+        )]);
+        let advantage = properties(&[(PROPERTY_ADVANTAGE, PropertyValue::IntValue(1))]);
+        let parallax = properties(&[(PROPERTY_PARALLAX_X, PropertyValue::BoolValue(true))]);
+
+        assert_eq!(
+            EnemyProperties::from_properties(&speed, &enemy_defaults()),
+            Err(PropertyError::WrongType {
+                name: PROPERTY_SPEED.to_string(),
+                expected: "float",
+            })
+        );
+        assert_eq!(
+            SpawnCondition::from_properties(&advantage),
+            Err(PropertyError::WrongType {
+                name: PROPERTY_ADVANTAGE.to_string(),
+                expected: "bool",
+            })
+        );
+        assert_eq!(
+            LayerProperties::from_properties(&parallax),
+            Err(PropertyError::WrongType {
+                name: PROPERTY_PARALLAX_X.to_string(),
+                expected: "float",
+            })
+        );
+    }
+
+    #[test]
+    fn bite_strength_must_fit_in_u8() {
+        let bite_strength = |value| {
+            let set = properties(&[(PROPERTY_BITE_STRENGTH, PropertyValue::IntValue(value))]);
+            EnemyProperties::from_properties(&set, &enemy_defaults())
+                .map(|properties| properties.bite_strength)
+        };
+        let out_of_range = Err(PropertyError::WrongType {
+            name: PROPERTY_BITE_STRENGTH.to_string(),
+            expected: "int from 0 to 255",
+        });
+
+        assert_eq!(bite_strength(0), Ok(0));
+        assert_eq!(bite_strength(255), Ok(255));
+        assert_eq!(bite_strength(256), out_of_range);
+        assert_eq!(bite_strength(-1), out_of_range);
+    }
+
+    #[test]
+    fn negative_speed_and_zero_interval_are_out_of_range() {
+        let speed = properties(&[(PROPERTY_SPEED, PropertyValue::FloatValue(-1.0))]);
+        let interval = properties(&[(PROPERTY_INTERVAL, PropertyValue::FloatValue(0.0))]);
+
+        assert_eq!(
+            EnemyProperties::from_properties(&speed, &enemy_defaults()),
+            Err(PropertyError::OutOfRange {
+                name: PROPERTY_SPEED.to_string(),
+                expected: "0 or more",
+            })
+        );
+        assert_eq!(
+            BubbleGeneratorProperties::from_properties(&interval),
+            Err(PropertyError::OutOfRange {
+                name: PROPERTY_INTERVAL.to_string(),
+                expected: "more than 0",
+            })
+        );
+    }
+
+    #[test]
+    fn parallax_of_one_axis_overrides_both() {
+        let set = properties(&[
+            (PROPERTY_PARALLAX, PropertyValue::FloatValue(0.5)),
+            (PROPERTY_PARALLAX_Y, PropertyValue::FloatValue(1.0)),
+        ]);
+
+        assert_eq!(
+            LayerProperties::from_properties(&set).map(|layer| layer.parallax),
+            Ok(Some(Vec2::new(0.5, 1.0)))
+        );
+    }
+}
@@ -0,0 +1,53 @@
+//! Game data kept in RON files under `assets`: levels, fish, sprite sheets and tuning.
+//! Every kind of file is read by the same loader, registered for its extension.
+
+use bevy::asset::{Asset, AssetLoader, LoadContext, LoadedAsset};
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::utils::BoxedFuture;
+use serde::de::DeserializeOwned;
+use std::marker::PhantomData;
+
+/// Reads assets of type `T` from RON files with one of `extensions`
+pub struct RonLoader<T> {
+    extensions: &'static [&'static str],
+    check: fn(&T) -> Result<(), anyhow::Error>,
+    asset: PhantomData<fn() -> T>,
+}
+
+impl<T: TypeUuid + DeserializeOwned> RonLoader<T> {
+    pub fn new(extensions: &'static [&'static str]) -> Self {
+        RonLoader {
+            extensions,
+            check: |_| Ok(()),
+            asset: PhantomData,
+        }
+    }
+
+    /// Rejects files that parse, but have values the game cannot run with
+    pub fn with_check(self, check: fn(&T) -> Result<(), anyhow::Error>) -> Self {
+        RonLoader { check, ..self }
+    }
+}
+
+impl<T: Asset + DeserializeOwned> AssetLoader for RonLoader<T> {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let asset: T = ron::de::from_bytes(bytes)?;
+            (self.check)(&asset)?;
+            load_context.set_default_asset(LoadedAsset::new(asset));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        self.extensions
+    }
+}
+
+/// Keeps a data file loaded for as long as the game runs
+pub struct RonHandle<T: Asset>(pub Handle<T>);
@@ -0,0 +1,197 @@
+//! Special attacks fish make while chasing, besides biting: the anglerfish lure pulls
+//! the player in, the sawfish dashes at them. Tuned per archetype in `assets/enemies.fish.ron`.
+
+use crate::ai::{AiState, Swims};
+use crate::animation::switch_clip;
+use crate::enemy::{Direction, FishAnimations};
+use crate::fish::{Attack, SpecialDef};
+use crate::player::{Damage, Player};
+use crate::{PlayerCollision, PlayerCollisionEventType};
+use benimator::SpriteSheetAnimation;
+use bevy::prelude::*;
+use heron::Velocity;
+
+/// Tint of a fish about to attack, it flashes between this and its own colors
+const TELEGRAPH_COLOR: Color = Color::rgb(1.0, 0.4, 0.4);
+/// Seconds the tint stays on or off
+const TELEGRAPH_FLASH: f32 = 0.1;
+
+/// Sensor covering the area a special attack reaches
+#[derive(Component)]
+pub struct SpecialHitbox;
+
+enum Phase {
+    Ready,
+    /// Telegraphs the attack, holding still
+    Windup(Timer),
+    Active(Timer),
+    Cooldown(Timer),
+}
+
+impl Phase {
+    fn is_attacking(&self) -> bool {
+        matches!(self, Phase::Windup(_) | Phase::Active(_))
+    }
+}
+
+#[derive(Component)]
+pub struct Special {
+    def: SpecialDef,
+    phase: Phase,
+    /// Player is inside the attack hitbox
+    touching: bool,
+}
+
+impl Special {
+    pub fn new(def: SpecialDef) -> Self {
+        Special {
+            def,
+            phase: Phase::Ready,
+            touching: false,
+        }
+    }
+
+    /// Fish moves on its own while attacking, instead of following its AI state
+    pub fn is_attacking(&self) -> bool {
+        self.phase.is_attacking()
+    }
+}
+
+/// Tracks the player entering and leaving attack hitboxes
+pub fn track_contacts(
+    mut player_collisions: EventReader<PlayerCollision>,
+    hitboxes: Query<(), With<SpecialHitbox>>,
+    mut specials: Query<&mut Special>,
+) {
+    for collision in player_collisions.iter() {
+        if hitboxes
+            .get(collision.other.collision_shape_entity())
+            .is_err()
+        {
+            continue;
+        }
+        if let Ok(mut special) = specials.get_mut(collision.other.rigid_body_entity()) {
+            special.touching = matches!(collision.event_type, PlayerCollisionEventType::Started);
+        }
+    }
+}
+
+/// Starts, times and carries out special attacks
+#[allow(clippy::type_complexity)]
+pub fn attack(
+    time: Res<Time>,
+    mut damages: EventWriter<Damage>,
+    mut player: Query<(&Transform, &mut Velocity), With<Player>>,
+    mut fish: Query<
+        (
+            &mut Special,
+            &AiState,
+            &mut Direction,
+            &mut Velocity,
+            &mut TextureAtlasSprite,
+            &mut Handle<SpriteSheetAnimation>,
+            &FishAnimations,
+            &Transform,
+            Option<&Swims>,
+        ),
+        Without<Player>,
+    >,
+) {
+    let (player_transform, mut player_velocity) = match player.get_single_mut() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+    let player_position = player_transform.translation.truncate();
+
+    for (
+        mut special,
+        state,
+        mut direction,
+        mut velocity,
+        mut sprite,
+        mut animation,
+        animations,
+        transform,
+        swims,
+    ) in fish.iter_mut()
+    {
+        let Special {
+            def,
+            phase,
+            touching,
+        } = &mut *special;
+        let position = transform.translation.truncate();
+
+        if matches!(state, AiState::Stunned(_)) && phase.is_attacking() {
+            // Getting hit interrupts the attack
+            *phase = Phase::Cooldown(Timer::from_seconds(def.cooldown, false));
+        }
+        let next = match phase {
+            Phase::Ready => (matches!(state, AiState::Chase { .. })
+                && position.distance(player_position) <= def.range)
+                .then(|| {
+                    *direction = Direction::towards(player_position.x - position.x);
+                    Phase::Windup(Timer::from_seconds(def.windup, false))
+                }),
+            Phase::Windup(timer) => {
+                timer.tick(time.delta());
+                timer
+                    .finished()
+                    .then(|| Phase::Active(Timer::from_seconds(def.duration, false)))
+            }
+            Phase::Active(timer) => {
+                timer.tick(time.delta());
+                timer
+                    .finished()
+                    .then(|| Phase::Cooldown(Timer::from_seconds(def.cooldown, false)))
+            }
+            Phase::Cooldown(timer) => {
+                timer.tick(time.delta());
+                timer.finished().then(|| Phase::Ready)
+            }
+        };
+        if let Some(next) = next {
+            *phase = next;
+        }
+
+        sprite.color = match phase {
+            Phase::Windup(timer) if (timer.elapsed_secs() / TELEGRAPH_FLASH) as u32 % 2 == 0 => {
+                TELEGRAPH_COLOR
+            }
+            _ => Color::WHITE,
+        };
+        if !phase.is_attacking() {
+            continue;
+        }
+
+        // Fish hold still while attacking, unless dashing
+        let speed = match (&*phase, &def.attack) {
+            (Phase::Active(_), Attack::Dash { speed, .. }) => *speed,
+            _ => 0.0,
+        };
+        velocity.linear.x = direction.sign() * speed;
+        if swims.is_some() {
+            velocity.linear.y = 0.0;
+        }
+
+        let clip = match *direction {
+            Direction::Left => &animations.special_left,
+            Direction::Right => &animations.special_right,
+        };
+        switch_clip(&mut animation, clip);
+
+        if !matches!(phase, Phase::Active(_)) || !*touching {
+            continue;
+        }
+        match def.attack {
+            Attack::Lure { pull } => {
+                let to_fish = (position - player_position).normalize_or_zero();
+                player_velocity.linear += (to_fish * pull * time.delta_seconds()).extend(0.0);
+            }
+            Attack::Dash { damage, .. } => damages.send(Damage {
+                amount: damage,
+                from: Some(position),
+            }),
+        }
+    }
+}
@@ -1,66 +1,55 @@
-use benimator::*;
+use crate::advantage::Advantage;
+use crate::animation::AnimationRegistry;
+use crate::enemy::Archetypes;
+use crate::levels::{Level, Levels, TmxMap};
+use crate::properties::{
+    is_conditional, BubbleGeneratorProperties, EnemyProperties, LayerProperties, SpawnCondition,
+    StarProperties,
+};
+use crate::tmx::{
+    layer_tiles, position_tmx_to_world, tileset_size, Bounds, CollisionTiles, LevelError,
+    LevelErrorKind, ObjectKind, TileRect, Waypoints, TILESET_HEIGHT, TILESET_WIDTH, TILE_SIZE,
+};
+use crate::water::{Oxygen, VolumeKind};
+use crate::AppState;
 use bevy::prelude::*;
+use bevy::render::mesh::Indices;
+use bevy::render::render_resource::PrimitiveTopology;
+use bevy::sprite::MaterialMesh2dBundle;
+use bevy::utils::HashMap;
 use heron::*;
-use rand::Rng;
-
-pub const TILE_SIZE: usize = 16;
 
 const TILESET_ASSET: &str = "terrain.png";
-static TILEMAPS_TMX: &[&[u8]] = &[
-    include_bytes!("../assets/levels/level3.tmx"),
-    include_bytes!("../assets/levels/level2.tmx"),
-];
-pub const MAPS_COUNT: usize = 2;
-
-const COLLISION_LAYER_NAME: &str = "collision";
-const OBJ_TYPE_PLAYER_START: &str = "player_start";
-const OBJ_TYPE_ANGLERFISH: &str = "anglerfish";
-const OBJ_TYPE_SAWFISH: &str = "sawfish";
-const OBJ_TYPE_STAR: &str = "star";
-const OBJ_TYPE_GOAL: &str = "goal";
-const OBJ_TYPE_BUBBLE_GENERATOR: &str = "bubble_generator";
-
-const TILESET_WIDTH: usize = 16;
-const TILESET_HEIGHT: usize = 5;
-
-#[derive(Clone)]
-pub enum CollisionTile {
-    Empty,
-    Full,
-}
 
-pub struct CollisionTiles {
-    pub width: usize,
-    pub height: usize,
-    pub tiles: Vec<Vec<CollisionTile>>,
-}
-
-impl CollisionTiles {
-    fn new(width: usize, height: usize) -> Self {
-        let row = vec![CollisionTile::Empty; width];
-        let mut tiles = Vec::with_capacity(height);
-        for _ in 0..height {
-            tiles.push(row.clone());
-        }
-        CollisionTiles {
-            width,
-            height,
-            tiles,
-        }
-    }
-}
+/// Static layers are baked into meshes of this many tiles square
+const CHUNK_SIZE: i32 = 32;
 
 #[derive(Component, Debug)]
 pub struct Map {
     pub width: usize,
     pub height: usize,
     pub index: usize,
+    pub name: String,
+}
+
+/// Layer that scrolls slower (or faster) than the world, 1.0 scrolls together with it
+#[derive(Component)]
+pub struct Parallax {
+    pub factor: Vec2,
+    /// Point where the layer is drawn at its place in Tiled, center of the map
+    pub anchor: Vec2,
 }
 
 pub struct ChangeMap {
     pub index: usize,
 }
 
+/// Level that could not be loaded, shown on screen until its file is fixed
+pub struct BrokenLevel {
+    pub index: Option<usize>,
+    pub error: LevelError,
+}
+
 fn create_tilemap_atlas(
     asset_server: &Res<AssetServer>,
     texture_atlases: &mut ResMut<Assets<TextureAtlas>>,
@@ -75,235 +64,518 @@ fn create_tilemap_atlas(
     texture_atlases.add(texture_atlas)
 }
 
-fn clear_map(
-    commands: &mut Commands,
-    map_query: &Query<Entity, With<Map>>,
-    player_query: &Query<Entity, With<crate::player::Player>>,
-    enemy_query: &Query<Entity, With<crate::enemy::Enemy>>,
-) {
-    let map = map_query
-        .get_single()
-        .expect("Map must be loaded (and only single instance)");
-    commands.entity(map).despawn_recursive();
-    for entity in player_query.iter() {
-        commands.entity(entity).despawn();
-    }
-    for entity in enemy_query.iter() {
-        commands.entity(entity).despawn();
+/// Everything spawned for a level
+type LevelEntityFilter = Or<(
+    With<Map>,
+    With<crate::player::Player>,
+    With<crate::enemy::Enemy>,
+    With<crate::Star>,
+    With<crate::goal::Goal>,
+    With<crate::bubble::BubbleGenerator>,
+    With<crate::bubble::Bubble>,
+    With<crate::water::Volume>,
+)>;
+
+fn clear_map(commands: &mut Commands, level_entities: &Query<Entity, LevelEntityFilter>) {
+    // Enemies have their colliders as children, leaving them behind confuses physics
+    for entity in level_entities.iter() {
+        commands.entity(entity).despawn_recursive();
     }
 
     info!("Cleared map");
 }
 
-pub fn load_initial_map(
-    mut commands: Commands,
-    asset_server: Res<AssetServer>,
-    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
-    mut animations: ResMut<Assets<SpriteSheetAnimation>>,
-    mut animation_handles: ResMut<crate::enemy::Animations>,
+fn parse_level(
+    levels: &Res<Levels>,
+    tmx_maps: &Res<Assets<TmxMap>>,
+    archetypes: &Archetypes,
+    index: usize,
+) -> Result<tiled::Map, LevelError> {
+    let level = &levels.0[index];
+    match tmx_maps.get(&level.map) {
+        Some(tmx) => crate::tmx::load(&tmx.bytes, &level.info.file, &archetypes.types()),
+        None => Err(LevelError::new(
+            &level.info.file,
+            LevelErrorKind::LoadFailed,
+        )),
+    }
+}
+
+pub fn show_broken_level(
+    commands: &mut Commands,
+    app_state: &mut ResMut<State<AppState>>,
+    index: Option<usize>,
+    error: LevelError,
 ) {
-    let mut rng = rand::thread_rng();
-    let index = rng.gen_range(0..2);
-    load_map(
-        &mut commands,
-        &asset_server,
-        &mut texture_atlases,
-        &mut animations,
-        &mut animation_handles,
-        index,
-    );
+    error!("{}", error);
+    commands.insert_resource(BrokenLevel { index, error });
+    app_state.set(AppState::BrokenLevel).unwrap();
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn handle_change_map(
     mut commands: Commands,
-    map_query: Query<Entity, With<Map>>,
+    level_entities: Query<Entity, LevelEntityFilter>,
     asset_server: Res<AssetServer>,
     mut texture_atlases: ResMut<Assets<TextureAtlas>>,
-    mut animations: ResMut<Assets<SpriteSheetAnimation>>,
-    mut animation_handles: ResMut<crate::enemy::Animations>,
-    player_query: Query<Entity, With<crate::player::Player>>,
-    enemy_query: Query<Entity, With<crate::enemy::Enemy>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    registry: Res<AnimationRegistry>,
+    archetypes: Res<Archetypes>,
+    mut advantage: ResMut<Advantage>,
+    mut oxygen: ResMut<Oxygen>,
+    levels: Res<Levels>,
+    tmx_maps: Res<Assets<TmxMap>>,
+    mut app_state: ResMut<State<AppState>>,
     mut change_map_reader: EventReader<ChangeMap>,
 ) {
-    for event in change_map_reader.iter() {
-        clear_map(&mut commands, &map_query, &player_query, &enemy_query);
+    // Goal sensor can report several contacts in one frame, only the last request matters
+    if let Some(event) = change_map_reader.iter().last() {
+        let map = match parse_level(&levels, &tmx_maps, &archetypes, event.index) {
+            Ok(map) => map,
+            Err(error) => {
+                show_broken_level(&mut commands, &mut app_state, Some(event.index), error);
+                return;
+            }
+        };
+
+        clear_map(&mut commands, &level_entities);
+
+        *advantage = Advantage::random_from(&levels.0[event.index].info.advantages);
+        // The player starts every map, and every retry, with full lungs
+        *oxygen = Oxygen::default();
+
         load_map(
             &mut commands,
             &asset_server,
             &mut texture_atlases,
-            &mut animations,
-            &mut animation_handles,
+            &mut meshes,
+            &mut materials,
+            &registry,
+            &archetypes,
+            &levels.0[event.index],
+            &map,
             event.index,
+            &advantage,
+            None,
         );
     }
 }
 
+/// Rebuilds current map when its TMX file changes on disk
+#[allow(clippy::too_many_arguments)]
+pub fn reload_changed_map(
+    mut commands: Commands,
+    level_entities: Query<Entity, LevelEntityFilter>,
+    maps: Query<&Map>,
+    asset_server: Res<AssetServer>,
+    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    registry: Res<AnimationRegistry>,
+    archetypes: Res<Archetypes>,
+    player_transform: Query<&Transform, With<crate::player::Player>>,
+    advantage: Res<Advantage>,
+    levels: Res<Levels>,
+    tmx_maps: Res<Assets<TmxMap>>,
+    mut app_state: ResMut<State<AppState>>,
+    mut asset_events: EventReader<AssetEvent<TmxMap>>,
+) {
+    let index = match maps.get_single() {
+        Ok(map) => map.index,
+        Err(_) => return,
+    };
+
+    let current_map_changed = asset_events.iter().any(
+        |event| matches!(event, AssetEvent::Modified { handle } if *handle == levels.0[index].map),
+    );
+    if !current_map_changed {
+        return;
+    }
+
+    info!("{} changed, reloading", levels.0[index].info.file);
+    let map = match parse_level(&levels, &tmx_maps, &archetypes, index) {
+        Ok(map) => map,
+        Err(error) => {
+            show_broken_level(&mut commands, &mut app_state, Some(index), error);
+            return;
+        }
+    };
+    let player_position = player_transform
+        .get_single()
+        .ok()
+        .map(|transform| transform.translation.truncate());
+
+    clear_map(&mut commands, &level_entities);
+    load_map(
+        &mut commands,
+        &asset_server,
+        &mut texture_atlases,
+        &mut meshes,
+        &mut materials,
+        &registry,
+        &archetypes,
+        &levels.0[index],
+        &map,
+        index,
+        &advantage,
+        player_position,
+    );
+}
+
+/// Tries again once the broken level file is saved
+pub fn retry_broken_level(
+    broken_level: Res<BrokenLevel>,
+    levels: Option<Res<Levels>>,
+    maps: Query<&Map>,
+    mut app_state: ResMut<State<AppState>>,
+    mut asset_events: EventReader<AssetEvent<TmxMap>>,
+    mut change_map_writer: EventWriter<ChangeMap>,
+) {
+    let (index, levels) = match (broken_level.index, levels) {
+        (Some(index), Some(levels)) => (index, levels),
+        _ => return,
+    };
+
+    let fixed = asset_events.iter().any(|event| match event {
+        AssetEvent::Created { handle } | AssetEvent::Modified { handle } => {
+            *handle == levels.0[index].map
+        }
+        AssetEvent::Removed { .. } => false,
+    });
+    if !fixed {
+        return;
+    }
+
+    if maps.iter().next().is_none() {
+        // Failed before the first map was spawned, go through loading again
+        app_state.set(AppState::Loading).unwrap();
+    } else {
+        change_map_writer.send(ChangeMap { index });
+        app_state.set(AppState::InGame).unwrap();
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn load_map(
     commands: &mut Commands,
     asset_server: &Res<AssetServer>,
     texture_atlases: &mut ResMut<Assets<TextureAtlas>>,
-    animations: &mut ResMut<Assets<SpriteSheetAnimation>>,
-    animation_handles: &mut ResMut<crate::enemy::Animations>,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    materials: &mut ResMut<Assets<ColorMaterial>>,
+    registry: &AnimationRegistry,
+    archetypes: &Archetypes,
+    level: &Level,
+    map: &tiled::Map,
     index: usize,
+    advantage: &Advantage,
+    player_position: Option<Vec2>,
 ) {
-    let map = tiled::parse(TILEMAPS_TMX[index]).unwrap();
-    let texture_atlas_handle = create_tilemap_atlas(asset_server, texture_atlases);
+    let enemy_types = archetypes.types();
+    let walker_types = archetypes.walker_types();
+    for warning in crate::tmx::lint(map, &level.info.file, &enemy_types, &walker_types) {
+        warn!("{}", warning);
+    }
 
-    let mut collision_tiles = CollisionTiles::new(map.width as usize, map.height as usize);
+    let texture_atlas_handle = create_tilemap_atlas(asset_server, texture_atlases);
+    let tileset_material = materials.add(ColorMaterial::from(asset_server.load(TILESET_ASSET)));
+    let tileset_size = tileset_size(map);
 
-    let width = map.width as usize;
-    let height = map.height as usize;
+    let collision_tiles = CollisionTiles::from_map(map);
+    let bounds = collision_tiles.bounds;
 
     let map_entity = commands
         .spawn()
         .insert(Map {
-            width,
-            height,
+            width: bounds.width,
+            height: bounds.height,
             index,
+            name: level.info.name.clone(),
         })
         .insert(Transform::default())
         .insert(GlobalTransform::default())
         .id();
 
+    let tile = TILE_SIZE as f32;
+    let map_center = bounds.tmx_to_world(
+        (bounds.left as f32 + bounds.width as f32 / 2.0) * tile,
+        (bounds.top as f32 + bounds.height as f32 / 2.0) * tile,
+    );
+
     let mut layer_index = 0;
     for layer in &map.layers {
         layer_index += 1;
-        if layer.name == "water" {
-            continue;
+        // Property types were checked when the level was validated
+        let properties = LayerProperties::from_properties(&layer.properties).unwrap_or_default();
+        let layer_entity = commands
+            .spawn_bundle((
+                Transform::default(),
+                GlobalTransform::default(),
+                Parent(map_entity),
+            ))
+            .id();
+        if let Some(factor) = properties.parallax {
+            commands.entity(layer_entity).insert(Parallax {
+                factor,
+                anchor: map_center,
+            });
         }
-        let is_collision_layer = layer.name == COLLISION_LAYER_NAME;
-        if let tiled::LayerData::Finite(tiles) = &layer.tiles {
-            for row in 0..height {
-                for col in 0..width {
-                    let tile = tiles[row][col];
-
-                    if tile.gid != 0 {
-                        create_tile_sprite(
-                            commands,
-                            map_entity.clone(),
-                            texture_atlas_handle.clone(),
-                            height,
-                            row,
-                            col,
-                            layer_index,
-                            tile.gid,
-                            is_collision_layer,
-                        );
 
-                        if is_collision_layer {
-                            collision_tiles.tiles[row][col] = CollisionTile::Full;
-                        }
-                    }
-                }
+        let tiles = layer_tiles(layer);
+        if properties.individual_tiles {
+            for (x, y, gid) in tiles {
+                create_tile_sprite(
+                    commands,
+                    layer_entity,
+                    texture_atlas_handle.clone(),
+                    bounds.tile_center(x, y),
+                    layer_index,
+                    gid,
+                );
             }
         } else {
-            unimplemented!();
+            create_layer_chunks(
+                commands,
+                meshes,
+                tileset_material.clone(),
+                layer_entity,
+                &bounds,
+                tileset_size,
+                &tiles,
+                layer_index,
+            );
         }
     }
 
-    let mut has_player_start = false;
+    let colliders = collision_tiles.merged_rects();
+    for rect in &colliders {
+        create_collider(commands, map_entity, &bounds, rect);
+    }
+    info!("Merged collision tiles into {} colliders", colliders.len());
+
     for object_group in &map.object_groups {
         for object in &object_group.objects {
-            if object.obj_type == OBJ_TYPE_PLAYER_START {
-                crate::player::spawn(
-                    commands,
-                    &asset_server,
-                    texture_atlases,
-                    animations,
-                    position_tmx_to_world(&map, object),
-                );
-                has_player_start = true;
-            } else if object.obj_type == OBJ_TYPE_ANGLERFISH {
-                crate::enemy::spawn_anglerfish(
-                    commands,
-                    asset_server,
-                    texture_atlases,
-                    animations,
-                    animation_handles,
-                    position_tmx_to_world(&map, object),
-                );
-            } else if object.obj_type == OBJ_TYPE_SAWFISH {
-                crate::enemy::spawn_sawfish(
-                    commands,
-                    asset_server,
-                    texture_atlases,
-                    animations,
-                    animation_handles,
-                    position_tmx_to_world(&map, object),
-                );
-            } else if object.obj_type == OBJ_TYPE_STAR {
-                crate::spawn_stars(
-                    commands,
-                    asset_server,
-                    position_tmx_to_world(&map, object),
-                    texture_atlases,
-                    animations,
-                );
-            } else if object.obj_type == OBJ_TYPE_GOAL {
-                if let tiled::ObjectShape::Rect { width, height } = object.shape {
-                    crate::goal::spawn(
+            let kind = match ObjectKind::of(&object.obj_type, &enemy_types) {
+                Some(kind) => kind,
+                None => continue,
+            };
+            if is_conditional(kind) {
+                let condition =
+                    SpawnCondition::from_properties(&object.properties).unwrap_or_default();
+                if !condition.is_met(kind == ObjectKind::Enemy, advantage) {
+                    continue;
+                }
+            }
+
+            match kind {
+                ObjectKind::PlayerStart => {
+                    // Keep player where they were on reload, unless map has changed under them
+                    let position = player_position
+                        .filter(|position| {
+                            collision_tiles.is_free(*position, crate::player::SIZE / 2.0)
+                        })
+                        .unwrap_or_else(|| position_tmx_to_world(&bounds, object));
+                    crate::player::spawn(commands, registry, position);
+                }
+                ObjectKind::Enemy => {
+                    let archetype = &archetypes.0[&object.obj_type];
+                    let defaults = archetype.def.properties();
+                    let properties =
+                        EnemyProperties::from_properties(&object.properties, &defaults)
+                            .unwrap_or(defaults);
+                    crate::enemy::spawn(
                         commands,
-                        position_tmx_to_world(&map, object),
-                        Vec2::new(width, height),
+                        archetype,
+                        position_tmx_to_world(&bounds, object),
+                        &properties,
+                        Waypoints::of_object(&bounds, object),
+                    );
+                }
+                ObjectKind::Star => {
+                    crate::spawn_stars(
+                        commands,
+                        registry,
+                        position_tmx_to_world(&bounds, object),
+                        &StarProperties::from_properties(&object.properties).unwrap_or_default(),
+                    );
+                }
+                ObjectKind::Goal => {
+                    if let tiled::ObjectShape::Rect { width, height } = object.shape {
+                        crate::goal::spawn(
+                            commands,
+                            position_tmx_to_world(&bounds, object),
+                            Vec2::new(width, height),
+                        );
+                    }
+                }
+                ObjectKind::Water | ObjectKind::AirPocket => {
+                    let volume = if kind == ObjectKind::Water {
+                        VolumeKind::Water
+                    } else {
+                        VolumeKind::AirPocket
+                    };
+                    if let tiled::ObjectShape::Rect { width, height } = object.shape {
+                        crate::water::spawn(
+                            commands,
+                            volume,
+                            position_tmx_to_world(&bounds, object),
+                            Vec2::new(width, height),
+                        );
+                    }
+                }
+                ObjectKind::BubbleGenerator => {
+                    crate::bubble::spawn_bubble_generator(
+                        commands,
+                        position_tmx_to_world(&bounds, object),
+                        &BubbleGeneratorProperties::from_properties(&object.properties)
+                            .unwrap_or_default(),
                     );
-                } else {
-                    panic!("Invalid goal shape, must be rectangle");
                 }
-            } else if object.obj_type == OBJ_TYPE_BUBBLE_GENERATOR {
-                crate::bubble::spawn_bubble_generator(
-                    commands,
-                    position_tmx_to_world(&map, object),
-                );
             }
         }
     }
-    if !has_player_start {
-        panic!("player_start not found in level #{index}");
-    }
-
     commands.insert_resource(collision_tiles);
-    info!("Loaded map {}", index);
+    info!("Loaded map {} ({})", index, level.info.file);
 }
 
 fn create_tile_sprite(
     commands: &mut Commands,
     map: Entity,
     texture_atlas_handle: Handle<TextureAtlas>,
-    height: usize,
-    row: usize,
-    col: usize,
+    position: Vec2,
     order: u32,
     tile_id: u32,
-    has_collision: bool,
 ) {
-    let position = Vec2::new(
-        (col * TILE_SIZE) as f32 + (TILE_SIZE as f32 / 2.0),
-        ((height - row) * TILE_SIZE) as f32 - (TILE_SIZE as f32 / 2.0),
-    );
-    let mut entity = commands.spawn_bundle(SpriteSheetBundle {
-        texture_atlas: texture_atlas_handle.clone(),
-        sprite: TextureAtlasSprite {
-            index: (tile_id as usize) - 1,
+    commands
+        .spawn_bundle(SpriteSheetBundle {
+            texture_atlas: texture_atlas_handle.clone(),
+            sprite: TextureAtlasSprite {
+                index: (tile_id as usize) - 1,
+                ..Default::default()
+            },
+            transform: Transform::from_xyz(position.x, position.y, order as f32),
             ..Default::default()
-        },
-        transform: Transform::from_xyz(position.x, position.y, order as f32),
-        ..Default::default()
-    });
-    entity.insert(Parent(map));
-    if has_collision {
-        entity.insert(RigidBody::Static);
-        entity.insert(CollisionShape::Cuboid {
-            half_extends: (Vec3::new(TILE_SIZE as f32 / 2.0, TILE_SIZE as f32 / 2.0, 0.0)),
-            border_radius: None,
-        });
+        })
+        .insert(Parent(map));
+}
+
+/// Offsets parallax layers so that they move `factor` times as fast as the camera
+pub fn scroll_parallax_layers(
+    camera: Query<&Transform, With<crate::MainCamera>>,
+    mut layers: Query<(&Parallax, &mut Transform), Without<crate::MainCamera>>,
+) {
+    let camera = match camera.get_single() {
+        Ok(camera) => camera.translation.truncate(),
+        Err(_) => return,
+    };
+
+    for (parallax, mut transform) in layers.iter_mut() {
+        let offset = (camera - parallax.anchor) * (Vec2::ONE - parallax.factor);
+        transform.translation.x = offset.x.round();
+        transform.translation.y = offset.y.round();
+    }
+}
+
+/// Bakes layer tiles into one mesh per chunk, instead of an entity per tile
+#[allow(clippy::too_many_arguments)]
+fn create_layer_chunks(
+    commands: &mut Commands,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    material: Handle<ColorMaterial>,
+    map: Entity,
+    bounds: &Bounds,
+    tileset_size: Vec2,
+    tiles: &[(i32, i32, u32)],
+    order: u32,
+) {
+    let mut chunks: HashMap<(i32, i32), Vec<(i32, i32, u32)>> = HashMap::default();
+    for &(x, y, gid) in tiles {
+        chunks
+            .entry((x.div_euclid(CHUNK_SIZE), y.div_euclid(CHUNK_SIZE)))
+            .or_insert_with(Vec::new)
+            .push((x, y, gid));
+    }
+
+    for chunk_tiles in chunks.values() {
+        commands
+            .spawn_bundle(MaterialMesh2dBundle {
+                mesh: meshes
+                    .add(tiles_mesh(bounds, tileset_size, chunk_tiles))
+                    .into(),
+                material: material.clone(),
+                transform: Transform::from_xyz(0.0, 0.0, order as f32),
+                ..Default::default()
+            })
+            .insert(Parent(map));
     }
 }
 
-fn position_tmx_to_world(map: &tiled::Map, object: &tiled::Object) -> Vec2 {
-    let map_height = (map.height * (TILE_SIZE as u32)) as f32;
+fn tiles_mesh(bounds: &Bounds, tileset_size: Vec2, tiles: &[(i32, i32, u32)]) -> Mesh {
+    let tile = TILE_SIZE as f32;
+    let half = tile / 2.0;
+    let columns = (tileset_size.x / tile) as u32;
+    let uv_tile_size = Vec2::splat(tile) / tileset_size;
+
+    let mut positions = Vec::with_capacity(tiles.len() * 4);
+    let mut normals: Vec<[f32; 3]> = Vec::with_capacity(tiles.len() * 4);
+    let mut uvs = Vec::with_capacity(tiles.len() * 4);
+    let mut indices = Vec::with_capacity(tiles.len() * 6);
+
+    for &(x, y, gid) in tiles {
+        let center = bounds.tile_center(x, y);
+        let index = gid - 1;
+        let uv_min = Vec2::new((index % columns) as f32, (index / columns) as f32) * uv_tile_size;
+        let uv_max = uv_min + uv_tile_size;
 
-    Vec2::new(
-        object.x + (object.width / 2.0),
-        map_height - object.y - (object.height / 2.0),
-    )
+        let first_vertex = positions.len() as u32;
+        positions.extend([
+            [center.x - half, center.y - half, 0.0],
+            [center.x + half, center.y - half, 0.0],
+            [center.x + half, center.y + half, 0.0],
+            [center.x - half, center.y + half, 0.0],
+        ]);
+        normals.extend([[0.0, 0.0, 1.0]; 4]);
+        // Texture v goes down, world y goes up
+        uvs.extend([
+            [uv_min.x, uv_max.y],
+            [uv_max.x, uv_max.y],
+            [uv_max.x, uv_min.y],
+            [uv_min.x, uv_min.y],
+        ]);
+        indices.extend([
+            first_vertex,
+            first_vertex + 1,
+            first_vertex + 2,
+            first_vertex,
+            first_vertex + 2,
+            first_vertex + 3,
+        ]);
+    }
+
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.set_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.set_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    mesh.set_indices(Some(Indices::U32(indices)));
+    mesh
+}
+
+fn create_collider(commands: &mut Commands, map: Entity, bounds: &Bounds, rect: &TileRect) {
+    let tile = TILE_SIZE as f32;
+    let size = Vec2::new(rect.width as f32 * tile, rect.height as f32 * tile);
+    let center = bounds.tmx_to_world(
+        rect.x as f32 * tile + size.x / 2.0,
+        rect.y as f32 * tile + size.y / 2.0,
+    );
+
+    commands.spawn_bundle((
+        RigidBody::Static,
+        CollisionShape::Cuboid {
+            half_extends: (size / 2.0).extend(0.0),
+            border_radius: None,
+        },
+        Transform::from_translation(center.extend(0.0)),
+        GlobalTransform::default(),
+        Parent(map),
+    ));
 }
@@ -0,0 +1,1013 @@
+//! Parsing and validation of TMX levels. Does not touch the ECS world,
+//! so it can be shared with tools that run without a window.
+
+use crate::properties::{self, LayerProperties, PropertyError};
+use bevy::math::Vec2;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use std::fmt;
+
+pub const TILE_SIZE: usize = 16;
+
+pub const TILESET_WIDTH: usize = 16;
+pub const TILESET_HEIGHT: usize = 5;
+
+pub const COLLISION_LAYER_NAME: &str = "collision";
+pub const OBJ_TYPE_PLAYER_START: &str = "player_start";
+pub const OBJ_TYPE_STAR: &str = "star";
+pub const OBJ_TYPE_GOAL: &str = "goal";
+pub const OBJ_TYPE_BUBBLE_GENERATOR: &str = "bubble_generator";
+pub const OBJ_TYPE_WATER: &str = "water";
+pub const OBJ_TYPE_AIR_POCKET: &str = "air_pocket";
+
+/// Pathfinding cost of a step to a side tile, diagonal steps cost about √2 times as much
+const STEP_COST: u32 = 10;
+const DIAGONAL_STEP_COST: u32 = 14;
+
+/// What a TMX object spawns, enemies have one object type per fish archetype
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ObjectKind {
+    PlayerStart,
+    Enemy,
+    Star,
+    Goal,
+    BubbleGenerator,
+    Water,
+    AirPocket,
+}
+
+impl ObjectKind {
+    /// Kind of object with type `obj_type`, `enemy_types` are names of fish archetypes
+    pub fn of(obj_type: &str, enemy_types: &[String]) -> Option<Self> {
+        match obj_type {
+            OBJ_TYPE_PLAYER_START => Some(ObjectKind::PlayerStart),
+            OBJ_TYPE_STAR => Some(ObjectKind::Star),
+            OBJ_TYPE_GOAL => Some(ObjectKind::Goal),
+            OBJ_TYPE_BUBBLE_GENERATOR => Some(ObjectKind::BubbleGenerator),
+            OBJ_TYPE_WATER => Some(ObjectKind::Water),
+            OBJ_TYPE_AIR_POCKET => Some(ObjectKind::AirPocket),
+            _ if enemy_types.iter().any(|enemy| enemy == obj_type) => Some(ObjectKind::Enemy),
+            _ => None,
+        }
+    }
+
+    /// Objects that get stuck when placed inside a wall
+    fn is_solid(self) -> bool {
+        matches!(
+            self,
+            ObjectKind::PlayerStart | ObjectKind::Enemy | ObjectKind::Star
+        )
+    }
+
+    /// Water and air pockets, areas the player swims or breathes in
+    fn is_volume(self) -> bool {
+        matches!(self, ObjectKind::Water | ObjectKind::AirPocket)
+    }
+}
+
+/// Tiles covered by a map, in Tiled tile coordinates. Fixed size maps start at `(0, 0)`,
+/// infinite maps grow in chunks and can reach into negative coordinates.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bounds {
+    pub left: i32,
+    pub top: i32,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Bounds {
+    pub fn of_map(map: &tiled::Map) -> Self {
+        let mut chunks = map
+            .layers
+            .iter()
+            .filter_map(|layer| match &layer.tiles {
+                tiled::LayerData::Infinite(chunks) => Some(chunks.values()),
+                tiled::LayerData::Finite(_) => None,
+            })
+            .flatten()
+            .peekable();
+        if chunks.peek().is_none() {
+            return Bounds {
+                left: 0,
+                top: 0,
+                width: map.width as usize,
+                height: map.height as usize,
+            };
+        }
+
+        let (mut left, mut top) = (i32::MAX, i32::MAX);
+        let (mut right, mut bottom) = (i32::MIN, i32::MIN);
+        for chunk in chunks {
+            left = left.min(chunk.x);
+            top = top.min(chunk.y);
+            right = right.max(chunk.x + chunk.width as i32);
+            bottom = bottom.max(chunk.y + chunk.height as i32);
+        }
+        Bounds {
+            left,
+            top,
+            width: (right - left) as usize,
+            height: (bottom - top) as usize,
+        }
+    }
+
+    fn bottom(&self) -> i32 {
+        self.top + self.height as i32
+    }
+
+    /// World position of a point given in Tiled pixels. World y goes up and
+    /// starts at the bottom edge of the map.
+    pub fn tmx_to_world(&self, x: f32, y: f32) -> Vec2 {
+        Vec2::new(x, (self.bottom() * TILE_SIZE as i32) as f32 - y)
+    }
+
+    pub fn tile_center(&self, x: i32, y: i32) -> Vec2 {
+        let tile = TILE_SIZE as f32;
+        self.tmx_to_world((x as f32 + 0.5) * tile, (y as f32 + 0.5) * tile)
+    }
+
+    /// Tiled tile coordinates containing a world position
+    pub fn world_to_tile(&self, position: Vec2) -> (i32, i32) {
+        let tile = TILE_SIZE as f32;
+        (
+            (position.x / tile).floor() as i32,
+            self.bottom() - (position.y / tile).floor() as i32 - 1,
+        )
+    }
+
+    /// `[row][col]` index of a tile in map sized storage, `None` outside of the map
+    pub fn index(&self, x: i32, y: i32) -> Option<(usize, usize)> {
+        let (col, row) = (x - self.left, y - self.top);
+        if col < 0 || row < 0 || col as usize >= self.width || row as usize >= self.height {
+            None
+        } else {
+            Some((row as usize, col as usize))
+        }
+    }
+}
+
+/// Non-empty tiles of a layer as `(x, y, gid)`, in Tiled tile coordinates
+pub fn layer_tiles(layer: &tiled::Layer) -> Vec<(i32, i32, u32)> {
+    match &layer.tiles {
+        tiled::LayerData::Finite(tiles) => tiles
+            .iter()
+            .enumerate()
+            .flat_map(|(y, row)| {
+                row.iter()
+                    .enumerate()
+                    .filter(|(_, tile)| tile.gid != 0)
+                    .map(move |(x, tile)| (x as i32, y as i32, tile.gid))
+            })
+            .collect(),
+        tiled::LayerData::Infinite(chunks) => chunks
+            .values()
+            .flat_map(|chunk| {
+                chunk.tiles.iter().enumerate().flat_map(move |(y, row)| {
+                    row.iter()
+                        .enumerate()
+                        .filter(|(_, tile)| tile.gid != 0)
+                        .map(move |(x, tile)| (chunk.x + x as i32, chunk.y + y as i32, tile.gid))
+                })
+            })
+            .collect(),
+    }
+}
+
+/// Size of the tileset image in pixels, as recorded in the TMX file
+pub fn tileset_size(map: &tiled::Map) -> Vec2 {
+    map.tilesets
+        .first()
+        .and_then(|tileset| tileset.images.first())
+        .map(|image| Vec2::new(image.width as f32, image.height as f32))
+        .unwrap_or_else(|| {
+            Vec2::new(
+                (TILESET_WIDTH * TILE_SIZE) as f32,
+                (TILESET_HEIGHT * TILE_SIZE) as f32,
+            )
+        })
+}
+
+/// Rectangle of tiles, in Tiled tile coordinates
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TileRect {
+    pub x: i32,
+    pub y: i32,
+    pub width: usize,
+    pub height: usize,
+}
+
+#[derive(Clone)]
+pub enum CollisionTile {
+    Empty,
+    Full,
+}
+
+pub struct CollisionTiles {
+    pub bounds: Bounds,
+    pub tiles: Vec<Vec<CollisionTile>>,
+}
+
+impl CollisionTiles {
+    pub fn new(bounds: Bounds) -> Self {
+        CollisionTiles {
+            bounds,
+            tiles: vec![vec![CollisionTile::Empty; bounds.width]; bounds.height],
+        }
+    }
+
+    /// Every non-empty tile of the collision layer is solid
+    pub fn from_map(map: &tiled::Map) -> Self {
+        let mut collision_tiles = CollisionTiles::new(Bounds::of_map(map));
+
+        for layer in &map.layers {
+            if layer.name != COLLISION_LAYER_NAME {
+                continue;
+            }
+            for (x, y, _) in layer_tiles(layer) {
+                if let Some((row, col)) = collision_tiles.bounds.index(x, y) {
+                    collision_tiles.tiles[row][col] = CollisionTile::Full;
+                }
+            }
+        }
+
+        collision_tiles
+    }
+
+    /// Whether a tile is inside the map and not solid
+    pub fn is_empty(&self, x: i32, y: i32) -> bool {
+        match self.bounds.index(x, y) {
+            Some((row, col)) => matches!(self.tiles[row][col], CollisionTile::Empty),
+            None => false,
+        }
+    }
+
+    /// Whether a box is inside the map and does not overlap any solid tile
+    pub fn is_free(&self, center: Vec2, half_size: Vec2) -> bool {
+        let (left, top) = self
+            .bounds
+            .world_to_tile(Vec2::new(center.x - half_size.x, center.y + half_size.y));
+        let (right, bottom) = self
+            .bounds
+            .world_to_tile(Vec2::new(center.x + half_size.x, center.y - half_size.y));
+
+        (top..=bottom).all(|y| (left..=right).all(|x| self.is_empty(x, y)))
+    }
+
+    /// Whether a straight line between two world positions stays clear of solid tiles
+    pub fn line_of_sight(&self, from: Vec2, to: Vec2) -> bool {
+        // Quarter tile steps can only miss a wall by clipping its corner
+        let step = TILE_SIZE as f32 / 4.0;
+        let steps = (from.distance(to) / step).ceil().max(1.0) as usize;
+        (0..=steps).all(|i| {
+            let (x, y) = self
+                .bounds
+                .world_to_tile(from.lerp(to, i as f32 / steps as f32));
+            self.is_empty(x, y)
+        })
+    }
+
+    /// Covers solid tiles with as few rectangles as this greedy pass can find.
+    /// Rows are merged first, so floors and ceilings end up as single long bodies
+    /// without seams for the player to snag on.
+    pub fn merged_rects(&self) -> Vec<TileRect> {
+        let Bounds { width, height, .. } = self.bounds;
+        let is_full = |row: usize, col: usize| matches!(self.tiles[row][col], CollisionTile::Full);
+        let mut covered = vec![vec![false; width]; height];
+        let mut rects = Vec::new();
+
+        for row in 0..height {
+            let mut col = 0;
+            while col < width {
+                let is_free_full = |row: usize, col: usize| is_full(row, col) && !covered[row][col];
+                if !is_free_full(row, col) {
+                    col += 1;
+                    continue;
+                }
+
+                let mut rect_width = 1;
+                while col + rect_width < width && is_free_full(row, col + rect_width) {
+                    rect_width += 1;
+                }
+
+                // Only grow down while the row below is exactly as wide, a wider row
+                // is a floor that should stay in one piece
+                let mut rect_height = 1;
+                while row + rect_height < height
+                    && (col..col + rect_width).all(|c| is_free_full(row + rect_height, c))
+                    && (col == 0 || !is_free_full(row + rect_height, col - 1))
+                    && (col + rect_width == width
+                        || !is_free_full(row + rect_height, col + rect_width))
+                {
+                    rect_height += 1;
+                }
+
+                for covered_row in &mut covered[row..row + rect_height] {
+                    for covered_tile in &mut covered_row[col..col + rect_width] {
+                        *covered_tile = true;
+                    }
+                }
+                rects.push(TileRect {
+                    x: self.bounds.left + col as i32,
+                    y: self.bounds.top + row as i32,
+                    width: rect_width,
+                    height: rect_height,
+                });
+                col += rect_width;
+            }
+        }
+
+        rects
+    }
+
+    /// Shortest path between two tiles, as every tile passed with both ends included.
+    /// Swimmers move in all eight directions through open water, without cutting
+    /// corners of walls. Walkers stay on the floor: they walk and fall, but never climb,
+    /// so both ends are first dropped to the floor below them.
+    pub fn find_path(
+        &self,
+        from: (i32, i32),
+        to: (i32, i32),
+        swims: bool,
+    ) -> Option<Vec<(i32, i32)>> {
+        let (from, to) = if swims {
+            (from, to)
+        } else {
+            (self.drop_to_floor(from), self.drop_to_floor(to))
+        };
+        if !self.is_empty(from.0, from.1) || !self.is_empty(to.0, to.1) {
+            return None;
+        }
+
+        // Octile distance, never more than the real cost
+        let estimate = |(x, y): (i32, i32)| {
+            let (dx, dy) = ((x - to.0).unsigned_abs(), (y - to.1).unsigned_abs());
+            STEP_COST * dx.max(dy) + (DIAGONAL_STEP_COST - STEP_COST) * dx.min(dy)
+        };
+        let mut open = BinaryHeap::new();
+        let mut costs = HashMap::new();
+        let mut came_from = HashMap::new();
+        open.push(Reverse((estimate(from), 0, from)));
+        costs.insert(from, 0);
+
+        while let Some(Reverse((_, cost, tile))) = open.pop() {
+            if tile == to {
+                let mut path = vec![tile];
+                while let Some(&previous) = came_from.get(path.last().unwrap()) {
+                    path.push(previous);
+                }
+                path.reverse();
+                return Some(path);
+            }
+            if cost > costs[&tile] {
+                // Already reached more cheaply
+                continue;
+            }
+            for (next, step_cost) in self.neighbours(tile, swims) {
+                let next_cost = cost + step_cost;
+                if costs.get(&next).map_or(true, |&known| next_cost < known) {
+                    costs.insert(next, next_cost);
+                    came_from.insert(next, tile);
+                    open.push(Reverse((next_cost + estimate(next), next_cost, next)));
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Tiles reachable in one step from `(x, y)`, with the cost of the step
+    fn neighbours(&self, (x, y): (i32, i32), swims: bool) -> Vec<((i32, i32), u32)> {
+        if !swims {
+            // Tiled y grows downwards. Walkers cannot steer while falling.
+            return if self.is_empty(x, y + 1) {
+                vec![((x, y + 1), STEP_COST)]
+            } else {
+                [(x - 1, y), (x + 1, y)]
+                    .into_iter()
+                    .filter(|&(x, y)| self.is_empty(x, y))
+                    .map(|tile| (tile, STEP_COST))
+                    .collect()
+            };
+        }
+
+        let mut neighbours = Vec::new();
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                if (dx, dy) == (0, 0) || !self.is_empty(x + dx, y + dy) {
+                    continue;
+                }
+                if dx == 0 || dy == 0 {
+                    neighbours.push(((x + dx, y + dy), STEP_COST));
+                } else if self.is_empty(x + dx, y) && self.is_empty(x, y + dy) {
+                    neighbours.push(((x + dx, y + dy), DIAGONAL_STEP_COST));
+                }
+            }
+        }
+        neighbours
+    }
+
+    /// First tile at or below `(x, y)` that stands on something solid
+    fn drop_to_floor(&self, (x, mut y): (i32, i32)) -> (i32, i32) {
+        while self.is_empty(x, y) && self.is_empty(x, y + 1) {
+            y += 1;
+        }
+        (x, y)
+    }
+
+    /// Empty tiles connected to the starting tile, indexed as `[row][col]`
+    fn flood_fill(&self, start_x: i32, start_y: i32) -> Vec<Vec<bool>> {
+        let mut reached = vec![vec![false; self.bounds.width]; self.bounds.height];
+        let mut queue = VecDeque::new();
+        if self.is_empty(start_x, start_y) {
+            queue.push_back((start_x, start_y));
+        }
+
+        while let Some((x, y)) = queue.pop_front() {
+            let (row, col) = match self.bounds.index(x, y) {
+                Some(index) => index,
+                None => continue,
+            };
+            if reached[row][col] {
+                continue;
+            }
+            reached[row][col] = true;
+
+            for (x, y) in [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)] {
+                if self.is_empty(x, y) {
+                    queue.push_back((x, y));
+                }
+            }
+        }
+
+        reached
+    }
+}
+
+pub fn position_tmx_to_world(bounds: &Bounds, object: &tiled::Object) -> Vec2 {
+    bounds.tmx_to_world(
+        object.x + (object.width / 2.0),
+        object.y + (object.height / 2.0),
+    )
+}
+
+/// Path drawn as a polyline or polygon object, in world coordinates
+#[derive(Clone, Debug, PartialEq)]
+pub struct Waypoints {
+    pub points: Vec<Vec2>,
+    /// Polygons are closed, the path goes from the last point back to the first
+    pub looped: bool,
+}
+
+impl Waypoints {
+    /// Points of a polyline or polygon, `None` for other shapes
+    pub fn of_object(bounds: &Bounds, object: &tiled::Object) -> Option<Self> {
+        let (points, looped) = match &object.shape {
+            tiled::ObjectShape::Polyline { points } => (points, false),
+            tiled::ObjectShape::Polygon { points } => (points, true),
+            _ => return None,
+        };
+        if points.len() < 2 {
+            return None;
+        }
+        Some(Waypoints {
+            points: points
+                .iter()
+                .map(|&(x, y)| bounds.tmx_to_world(object.x + x, object.y + y))
+                .collect(),
+            looped,
+        })
+    }
+
+    /// Point after `index` on the way along the path, and whether the way then goes
+    /// backwards. Open paths turn around at either end, closed ones start over.
+    pub fn step(&self, index: usize, backwards: bool) -> (usize, bool) {
+        let last = self.points.len() - 1;
+        if self.looped {
+            return (if index == last { 0 } else { index + 1 }, false);
+        }
+        let backwards = if index == last {
+            true
+        } else if index == 0 {
+            false
+        } else {
+            backwards
+        };
+        (if backwards { index - 1 } else { index + 1 }, backwards)
+    }
+
+    /// Points less than a pixel apart horizontally, a path walking fish cannot move along
+    pub fn is_vertical(&self) -> bool {
+        let x = self.points[0].x;
+        self.points.iter().all(|point| (point.x - x).abs() < 1.0)
+    }
+}
+
+#[derive(Debug)]
+pub enum LevelErrorKind {
+    /// Asset server could not read the file
+    LoadFailed,
+    /// Level manifest does not list any level
+    NoLevels,
+    Parse(String),
+    UnknownTile {
+        layer: String,
+        x: i32,
+        y: i32,
+        gid: u32,
+    },
+    MissingPlayerStart,
+    MissingGoal,
+    GoalNotRectangle,
+    GoalUnreachable,
+    /// Water or air pocket of another shape, with its object type
+    NotRectangle(String),
+    UnknownObjectType(String),
+    InsideWall(String),
+    /// Point of a patrol path, counted from 1 like in Tiled
+    WaypointInsideWall(usize),
+    /// Patrol path of a walking fish, with its object type
+    VerticalWalkerPath(String),
+    LayerProperty {
+        layer: String,
+        error: PropertyError,
+    },
+    ObjectProperty(PropertyError),
+}
+
+#[derive(Debug)]
+pub struct LevelError {
+    pub file: String,
+    pub object_id: Option<u32>,
+    pub kind: LevelErrorKind,
+}
+
+impl LevelError {
+    pub fn new(file: &str, kind: LevelErrorKind) -> Self {
+        LevelError {
+            file: file.to_string(),
+            object_id: None,
+            kind,
+        }
+    }
+
+    fn for_object(file: &str, object: &tiled::Object, kind: LevelErrorKind) -> Self {
+        LevelError {
+            file: file.to_string(),
+            object_id: Some(object.id),
+            kind,
+        }
+    }
+}
+
+impl fmt::Display for LevelErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LevelErrorKind::LoadFailed => write!(f, "file could not be loaded"),
+            LevelErrorKind::NoLevels => write!(f, "no levels listed"),
+            LevelErrorKind::Parse(message) => write!(f, "invalid TMX: {}", message),
+            LevelErrorKind::UnknownTile { layer, x, y, gid } => write!(
+                f,
+                "tile {} at ({}, {}) of layer \"{}\" is outside of the {}x{} tileset",
+                gid, x, y, layer, TILESET_WIDTH, TILESET_HEIGHT
+            ),
+            LevelErrorKind::MissingPlayerStart => write!(f, "no {} object", OBJ_TYPE_PLAYER_START),
+            LevelErrorKind::MissingGoal => write!(f, "no {} object", OBJ_TYPE_GOAL),
+            LevelErrorKind::GoalNotRectangle => write!(f, "goal must be a rectangle"),
+            LevelErrorKind::NotRectangle(obj_type) => {
+                write!(f, "{} must be a rectangle", obj_type)
+            }
+            LevelErrorKind::GoalUnreachable => {
+                write!(f, "goal cannot be reached from player start")
+            }
+            LevelErrorKind::UnknownObjectType(obj_type) => {
+                write!(f, "unknown object type \"{}\"", obj_type)
+            }
+            LevelErrorKind::InsideWall(obj_type) => {
+                write!(f, "{} is inside a wall or outside of the map", obj_type)
+            }
+            LevelErrorKind::WaypointInsideWall(point) => {
+                write!(
+                    f,
+                    "waypoint {} is inside a wall or outside of the map",
+                    point
+                )
+            }
+            LevelErrorKind::VerticalWalkerPath(obj_type) => write!(
+                f,
+                "\"{}\" walks and cannot patrol a path that only goes up and down",
+                obj_type
+            ),
+            LevelErrorKind::LayerProperty { layer, error } => {
+                write!(f, "layer \"{}\": {}", layer, error)
+            }
+            LevelErrorKind::ObjectProperty(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.object_id {
+            Some(id) => write!(f, "{}: object #{}: {}", self.file, id, self.kind),
+            None => write!(f, "{}: {}", self.file, self.kind),
+        }
+    }
+}
+
+impl std::error::Error for LevelError {}
+
+/// Parses and validates a level, returning the first problem found
+pub fn load(bytes: &[u8], file: &str, enemy_types: &[String]) -> Result<tiled::Map, LevelError> {
+    let map = tiled::parse(bytes)
+        .map_err(|err| LevelError::new(file, LevelErrorKind::Parse(err.to_string())))?;
+    match validate(&map, file, enemy_types).into_iter().next() {
+        Some(err) => Err(err),
+        None => Ok(map),
+    }
+}
+
+/// Lists everything that would stop the level from being spawned or finished
+pub fn validate(map: &tiled::Map, file: &str, enemy_types: &[String]) -> Vec<LevelError> {
+    let mut errors = Vec::new();
+    let tile_count = (TILESET_WIDTH * TILESET_HEIGHT) as u32;
+
+    for layer in &map.layers {
+        if let Err(error) = LayerProperties::from_properties(&layer.properties) {
+            errors.push(LevelError::new(
+                file,
+                LevelErrorKind::LayerProperty {
+                    layer: layer.name.clone(),
+                    error,
+                },
+            ));
+        }
+        for (x, y, gid) in layer_tiles(layer) {
+            if gid > tile_count {
+                errors.push(LevelError::new(
+                    file,
+                    LevelErrorKind::UnknownTile {
+                        layer: layer.name.clone(),
+                        x,
+                        y,
+                        gid,
+                    },
+                ));
+            }
+        }
+    }
+
+    let objects = map
+        .object_groups
+        .iter()
+        .flat_map(|group| group.objects.iter());
+    for object in objects.clone() {
+        let kind = match ObjectKind::of(&object.obj_type, enemy_types) {
+            Some(kind) => kind,
+            None => continue,
+        };
+        if let Err(error) = properties::check_object(object, kind) {
+            errors.push(LevelError::for_object(
+                file,
+                object,
+                LevelErrorKind::ObjectProperty(error),
+            ));
+        }
+        if kind.is_volume() && !matches!(object.shape, tiled::ObjectShape::Rect { .. }) {
+            errors.push(LevelError::for_object(
+                file,
+                object,
+                LevelErrorKind::NotRectangle(object.obj_type.clone()),
+            ));
+        }
+    }
+    let player_start = objects
+        .clone()
+        .find(|object| object.obj_type == OBJ_TYPE_PLAYER_START);
+    let goals: Vec<&tiled::Object> = objects
+        .filter(|object| object.obj_type == OBJ_TYPE_GOAL)
+        .collect();
+
+    if player_start.is_none() {
+        errors.push(LevelError::new(file, LevelErrorKind::MissingPlayerStart));
+    }
+    if goals.is_empty() {
+        errors.push(LevelError::new(file, LevelErrorKind::MissingGoal));
+    }
+    for goal in &goals {
+        if !matches!(goal.shape, tiled::ObjectShape::Rect { .. }) {
+            errors.push(LevelError::for_object(
+                file,
+                goal,
+                LevelErrorKind::GoalNotRectangle,
+            ));
+        }
+    }
+
+    // Only checks that there is open water between start and goal, not that jumps are possible
+    if let (Some(start), false) = (player_start, goals.is_empty()) {
+        let collision_tiles = CollisionTiles::from_map(map);
+        let tile = |pixels: f32| (pixels / TILE_SIZE as f32).floor() as i32;
+        let reached = collision_tiles.flood_fill(
+            tile(start.x + start.width / 2.0),
+            tile(start.y + start.height / 2.0),
+        );
+        for goal in goals {
+            let reachable = (tile(goal.y)..=tile(goal.y + goal.height)).any(|y| {
+                (tile(goal.x)..=tile(goal.x + goal.width)).any(|x| {
+                    match collision_tiles.bounds.index(x, y) {
+                        Some((row, col)) => reached[row][col],
+                        None => false,
+                    }
+                })
+            });
+            if !reachable {
+                errors.push(LevelError::for_object(
+                    file,
+                    goal,
+                    LevelErrorKind::GoalUnreachable,
+                ));
+            }
+        }
+    }
+
+    errors
+}
+
+/// Mistakes that do not stop the level from loading, but most likely break it.
+/// Objects without type are ignored, same as in game. `walker_types` are the
+/// enemy types that walk along the floor.
+pub fn lint(
+    map: &tiled::Map,
+    file: &str,
+    enemy_types: &[String],
+    walker_types: &[String],
+) -> Vec<LevelError> {
+    let collision_tiles = CollisionTiles::from_map(map);
+    let bounds = collision_tiles.bounds;
+
+    map.object_groups
+        .iter()
+        .flat_map(|group| group.objects.iter())
+        .filter(|object| !object.obj_type.is_empty())
+        .filter_map(|object| {
+            let kind = match ObjectKind::of(&object.obj_type, enemy_types) {
+                None => LevelErrorKind::UnknownObjectType(object.obj_type.clone()),
+                Some(kind) if kind.is_solid() => {
+                    let position = position_tmx_to_world(&bounds, object);
+                    if !collision_tiles.is_free(position, Vec2::ZERO) {
+                        LevelErrorKind::InsideWall(object.obj_type.clone())
+                    } else {
+                        let path = Waypoints::of_object(&bounds, object)?;
+                        let blocked = path
+                            .points
+                            .iter()
+                            .position(|point| !collision_tiles.is_free(*point, Vec2::ZERO));
+                        match blocked {
+                            Some(index) => LevelErrorKind::WaypointInsideWall(index + 1),
+                            None if path.is_vertical()
+                                && walker_types.contains(&object.obj_type) =>
+                            {
+                                LevelErrorKind::VerticalWalkerPath(object.obj_type.clone())
+                            }
+                            None => return None,
+                        }
+                    }
+                }
+                Some(_) => return None,
+            };
+            Some(LevelError::for_object(file, object, kind))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds collision tiles from rows of `#` (solid) and `.` (empty)
+    fn collision_tiles(left: i32, top: i32, rows: &[&str]) -> CollisionTiles {
+        let mut collision_tiles = CollisionTiles::new(Bounds {
+            left,
+            top,
+            width: rows[0].len(),
+            height: rows.len(),
+        });
+        for (row, line) in rows.iter().enumerate() {
+            for (col, c) in line.chars().enumerate() {
+                if c == '#' {
+                    collision_tiles.tiles[row][col] = CollisionTile::Full;
+                }
+            }
+        }
+        collision_tiles
+    }
+
+    fn rect(x: i32, y: i32, width: usize, height: usize) -> TileRect {
+        TileRect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Every solid tile is covered exactly once and no empty tile is covered
+    fn assert_exact_cover(collision_tiles: &CollisionTiles, rects: &[TileRect]) {
+        let bounds = collision_tiles.bounds;
+        let mut cover_count = vec![vec![0; bounds.width]; bounds.height];
+        for rect in rects {
+            for y in rect.y..rect.y + rect.height as i32 {
+                for x in rect.x..rect.x + rect.width as i32 {
+                    let (row, col) = bounds.index(x, y).expect("rect outside of the map");
+                    cover_count[row][col] += 1;
+                }
+            }
+        }
+        for row in 0..bounds.height {
+            for col in 0..bounds.width {
+                let expected = match collision_tiles.tiles[row][col] {
+                    CollisionTile::Full => 1,
+                    CollisionTile::Empty => 0,
+                };
+                assert_eq!(
+                    cover_count[row][col], expected,
+                    "tile at row {}, col {}",
+                    row, col
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn line_of_sight_is_blocked_by_wall() {
+        let collision_tiles = collision_tiles(0, 0, &["....", ".#..", "...."]);
+        let bounds = collision_tiles.bounds;
+        let sees = |from: (i32, i32), to: (i32, i32)| {
+            collision_tiles.line_of_sight(
+                bounds.tile_center(from.0, from.1),
+                bounds.tile_center(to.0, to.1),
+            )
+        };
+
+        assert!(sees((0, 0), (3, 0)));
+        assert!(!sees((0, 1), (3, 1)));
+        assert!(!sees((0, 0), (2, 2)));
+    }
+
+    #[test]
+    fn swimmer_goes_around_wall_without_cutting_corners() {
+        let collision_tiles = collision_tiles(0, 0, &["......", "..#...", "..#...", "......"]);
+        let path = collision_tiles.find_path((0, 1), (4, 1), true).unwrap();
+
+        assert_eq!(path, vec![(0, 1), (1, 0), (2, 0), (3, 0), (4, 1)]);
+    }
+
+    #[test]
+    fn walker_falls_but_does_not_climb() {
+        let collision_tiles = collision_tiles(0, 0, &["......", "......", "###...", "######"]);
+
+        let path = collision_tiles.find_path((0, 0), (5, 2), false).unwrap();
+        assert_eq!(
+            path,
+            vec![(0, 1), (1, 1), (2, 1), (3, 1), (3, 2), (4, 2), (5, 2)]
+        );
+        assert_eq!(collision_tiles.find_path((5, 2), (0, 1), false), None);
+    }
+
+    #[test]
+    fn no_path_into_closed_room() {
+        let collision_tiles = collision_tiles(0, 0, &["...###", "...#.#", "...###"]);
+        assert_eq!(collision_tiles.find_path((0, 0), (4, 1), true), None);
+    }
+
+    #[test]
+    fn empty_map_has_no_rects() {
+        let tiles = collision_tiles(0, 0, &["....", "...."]);
+        assert!(tiles.merged_rects().is_empty());
+    }
+
+    #[test]
+    fn floor_is_single_rect() {
+        let tiles = collision_tiles(0, 0, &["......", "......", "######"]);
+        assert_eq!(tiles.merged_rects(), vec![rect(0, 2, 6, 1)]);
+    }
+
+    #[test]
+    fn solid_block_is_single_rect() {
+        let tiles = collision_tiles(0, 0, &["......", ".###..", ".###..", ".###.."]);
+        assert_eq!(tiles.merged_rects(), vec![rect(1, 1, 3, 3)]);
+    }
+
+    #[test]
+    fn wall_on_floor_splits_by_rows_first() {
+        let tiles = collision_tiles(0, 0, &["#...", "#...", "####"]);
+        assert_eq!(
+            tiles.merged_rects(),
+            vec![rect(0, 0, 1, 2), rect(0, 2, 4, 1)]
+        );
+    }
+
+    #[test]
+    fn separate_platforms_are_separate_rects() {
+        let tiles = collision_tiles(0, 0, &["##..##", "......", "..##.."]);
+        let rects = tiles.merged_rects();
+        assert_eq!(rects.len(), 3);
+        assert_exact_cover(&tiles, &rects);
+    }
+
+    #[test]
+    fn rects_use_signed_origin() {
+        let tiles = collision_tiles(-32, -4, &["..", "##"]);
+        assert_eq!(tiles.merged_rects(), vec![rect(-32, -3, 2, 1)]);
+    }
+
+    #[test]
+    fn irregular_cave_is_covered_exactly() {
+        let tiles = collision_tiles(
+            0,
+            0,
+            &[
+                "############",
+                "##....###..#",
+                "#.....#....#",
+                "#..##......#",
+                "#..###..####",
+                "############",
+            ],
+        );
+        let rects = tiles.merged_rects();
+        assert_exact_cover(&tiles, &rects);
+        assert!(rects.len() < 20, "{} rects", rects.len());
+    }
+
+    fn waypoints(count: usize, looped: bool) -> Waypoints {
+        Waypoints {
+            points: (0..count).map(|x| Vec2::new(x as f32, 0.0)).collect(),
+            looped,
+        }
+    }
+
+    /// Indices of the points visited after the first, `steps` of them
+    fn walk(path: &Waypoints, steps: usize) -> Vec<usize> {
+        let (mut index, mut backwards) = (0, false);
+        (0..steps)
+            .map(|_| {
+                let (next, next_backwards) = path.step(index, backwards);
+                index = next;
+                backwards = next_backwards;
+                index
+            })
+            .collect()
+    }
+
+    #[test]
+    fn open_path_goes_back_and_forth() {
+        assert_eq!(walk(&waypoints(3, false), 6), vec![1, 2, 1, 0, 1, 2]);
+        assert_eq!(walk(&waypoints(2, false), 4), vec![1, 0, 1, 0]);
+    }
+
+    #[test]
+    fn closed_path_starts_over() {
+        assert_eq!(walk(&waypoints(3, true), 6), vec![1, 2, 0, 1, 2, 0]);
+    }
+
+    #[test]
+    fn polyline_points_are_in_world_coordinates() {
+        let map = tiled::parse(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.8" orientation="orthogonal" renderorder="right-down" width="4" height="4" tilewidth="16" tileheight="16" infinite="0" nextlayerid="2" nextobjectid="2">
+ <objectgroup id="1" name="objects">
+  <object id="1" type="crab" x="16" y="32">
+   <polyline points="0,0 32,0 32,-16"/>
+  </object>
+ </objectgroup>
+</map>"#
+                .as_bytes(),
+        )
+        .unwrap();
+        let object = &map.object_groups[0].objects[0];
+
+        let path = Waypoints::of_object(&Bounds::of_map(&map), object).unwrap();
+        assert_eq!(
+            path,
+            Waypoints {
+                points: vec![
+                    Vec2::new(16.0, 32.0),
+                    Vec2::new(48.0, 32.0),
+                    Vec2::new(48.0, 48.0)
+                ],
+                looped: false,
+            }
+        );
+        assert!(!path.is_vertical());
+    }
+
+    #[test]
+    fn path_straight_up_is_vertical() {
+        let path = Waypoints {
+            points: vec![Vec2::new(8.0, 0.0), Vec2::new(8.0, 64.0)],
+            looped: false,
+        };
+        assert!(path.is_vertical());
+    }
+}
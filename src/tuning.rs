@@ -0,0 +1,49 @@
+//! How the player moves, swims and takes bites, defined in `assets/player.tuning.ron`.
+//! Settings start at their defaults, are replaced once the file loads and again
+//! whenever it changes. Fields missing from the file keep their defaults.
+
+use crate::player::{ControllerSettings, DamageSettings};
+use crate::ron_asset::RonHandle;
+use crate::water::SwimSettings;
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use serde::Deserialize;
+
+/// Relative to assets directory
+pub const TUNING_PATH: &str = "player.tuning.ron";
+
+#[derive(Deserialize, TypeUuid, Default)]
+#[uuid = "ad53574d-5479-4325-9530-921e69691c21"]
+#[serde(default)]
+pub struct Tuning {
+    pub controller: ControllerSettings,
+    pub damage: DamageSettings,
+    pub swim: SwimSettings,
+}
+
+pub fn start_loading(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(RonHandle::<Tuning>(asset_server.load(TUNING_PATH)));
+}
+
+/// Replaces the settings with the ones from the file, again when it changes
+pub fn apply_tuning(
+    mut commands: Commands,
+    tunings: Res<Assets<Tuning>>,
+    mut asset_events: EventReader<AssetEvent<Tuning>>,
+) {
+    for event in asset_events.iter() {
+        let handle = match event {
+            AssetEvent::Created { handle } | AssetEvent::Modified { handle } => handle,
+            AssetEvent::Removed { .. } => continue,
+        };
+        let tuning = match tunings.get(handle) {
+            Some(tuning) => tuning,
+            None => continue,
+        };
+
+        commands.insert_resource(tuning.controller.clone());
+        commands.insert_resource(tuning.damage.clone());
+        commands.insert_resource(tuning.swim.clone());
+        info!("Loaded {}", TUNING_PATH);
+    }
+}
@@ -0,0 +1,167 @@
+//! Water the player swims in, and air pockets inside it to breathe. Both are rectangles
+//! in the object layer of a level, of type `water` and `air_pocket`. In water gravity
+//! mostly gives way to buoyancy, drag slows the player down and jump becomes a swim stroke.
+
+use crate::player::{Damage, Player};
+use bevy::prelude::*;
+use heron::Acceleration;
+use serde::Deserialize;
+
+const WATER_COLOR: Color = Color::rgba(0.1, 0.4, 0.8, 0.35);
+const AIR_POCKET_COLOR: Color = Color::rgba(0.8, 0.9, 1.0, 0.35);
+
+/// How the player swims and breathes, tuned under `swim`
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct SwimSettings {
+    /// Fraction of gravity cancelled in water, above 1.0 the player floats up
+    pub buoyancy: f32,
+    /// Fraction of vertical speed lost per second
+    pub drag: f32,
+    /// Swimming speed, in pixels per second
+    pub max_speed: f32,
+    /// Fraction of the acceleration and deceleration the player has on land
+    pub control: f32,
+    /// Upward speed of a stroke, one per press of jump
+    pub stroke_speed: f32,
+    pub dive_speed: f32,
+    /// Seconds the player can stay under water
+    pub breath: f32,
+    /// Seconds to fill the lungs from empty, out of water or in an air pocket
+    pub refill: f32,
+    /// Seconds between losing HP once out of air
+    pub drown_interval: f32,
+}
+
+impl Default for SwimSettings {
+    fn default() -> Self {
+        SwimSettings {
+            buoyancy: 0.85,
+            drag: 3.0,
+            max_speed: 140.0,
+            control: 0.5,
+            stroke_speed: 280.0,
+            dive_speed: 200.0,
+            breath: 15.0,
+            refill: 2.0,
+            drown_interval: 1.0,
+        }
+    }
+}
+
+/// Air left in the lungs of the player, 1.0 is full
+pub struct Oxygen(pub f32);
+
+impl Default for Oxygen {
+    fn default() -> Self {
+        Oxygen(1.0)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VolumeKind {
+    Water,
+    AirPocket,
+}
+
+/// Rectangle of water or air, centered on its transform
+#[derive(Component)]
+pub struct Volume {
+    pub kind: VolumeKind,
+    pub half_size: Vec2,
+}
+
+/// Whether the player is in water and out of air pockets, updated every frame by `detect_water`
+#[derive(Component, Default)]
+pub struct Swimming(pub bool);
+
+pub fn spawn(commands: &mut Commands, kind: VolumeKind, position: Vec2, size: Vec2) {
+    // Drawn over the player and fish, tinting everything inside
+    let (color, z) = match kind {
+        VolumeKind::Water => (WATER_COLOR, 8.0),
+        VolumeKind::AirPocket => (AIR_POCKET_COLOR, 9.0),
+    };
+    commands
+        .spawn_bundle(SpriteBundle {
+            sprite: Sprite {
+                color,
+                custom_size: Some(size),
+                ..Default::default()
+            },
+            transform: Transform::from_translation(position.extend(z)),
+            ..Default::default()
+        })
+        .insert(Volume {
+            kind,
+            half_size: size / 2.0,
+        });
+}
+
+/// Checks which volumes the center of the player is in, and sets gravity to match
+pub fn detect_water(
+    settings: Res<SwimSettings>,
+    volumes: Query<(&Volume, &Transform)>,
+    mut player: Query<(&Transform, &mut Swimming, &mut Acceleration), With<Player>>,
+) {
+    let (transform, mut swimming, mut acceleration) = match player.get_single_mut() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+    let position = transform.translation.truncate();
+    let is_in = |kind: VolumeKind| {
+        volumes.iter().any(|(volume, volume_transform)| {
+            let offset = (position - volume_transform.translation.truncate()).abs();
+            volume.kind == kind && offset.x <= volume.half_size.x && offset.y <= volume.half_size.y
+        })
+    };
+
+    let in_water = is_in(VolumeKind::Water) && !is_in(VolumeKind::AirPocket);
+    if swimming.0 == in_water {
+        return;
+    }
+    swimming.0 = in_water;
+    let buoyancy = if in_water {
+        -crate::GRAVITY * settings.buoyancy
+    } else {
+        0.0
+    };
+    *acceleration = Acceleration::from_linear(Vec3::new(0.0, buoyancy, 0.0));
+}
+
+/// Uses up air under water and refills it outside, the player drowns once it runs out
+pub fn breathe(
+    time: Res<Time>,
+    settings: Res<SwimSettings>,
+    mut oxygen: ResMut<Oxygen>,
+    mut drowning: Local<f32>,
+    player: Query<&Swimming, With<Player>>,
+    mut damages: EventWriter<Damage>,
+) {
+    let swimming = match player.get_single() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+    let dt = time.delta_seconds();
+
+    if !swimming.0 {
+        // Full lungs are left alone, for change detection of the meter
+        if oxygen.0 < 1.0 {
+            oxygen.0 = (oxygen.0 + dt / settings.refill).min(1.0);
+        }
+        *drowning = 0.0;
+        return;
+    }
+    oxygen.0 = (oxygen.0 - dt / settings.breath).max(0.0);
+    if oxygen.0 > 0.0 {
+        return;
+    }
+
+    *drowning -= dt;
+    if *drowning <= 0.0 {
+        *drowning = settings.drown_interval;
+        damages.send(Damage {
+            amount: 1,
+            from: None,
+        });
+    }
+}